@@ -0,0 +1,421 @@
+//! Headless games of one or two snakes, used by the server, the bots and
+//! tournaments, where there is no window to draw in. An `Arena` runs a
+//! `World` on a plain board size, so these games play by the same rules as
+//! the window does, and keeps the match around it: who plays, who gave up
+//! and when the next snake is due on the game clock.
+
+use crate::clock::SystemClock;
+use crate::game_loop::Schedule;
+use crate::topology::Topology;
+use crate::{snake_generator, BoardSize, Cli, DifficultyProfile, Direction, World};
+use clap::Parser;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use web_time::Duration;
+
+/// Longest side of a board. Every board is sent whole to the players and
+/// the bots, so this keeps what a client can ask of the server in check.
+pub const MAX_BOARD_SIDE: usize = 200;
+
+/// Most snakes in one game, as many as a `World` has.
+pub const MAX_PLAYERS: usize = 2;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ArenaSettings {
+    pub width: usize,
+    pub height: usize,
+    pub snake_size_start: usize,
+    pub topology: Topology,
+    pub bad_berries: bool,
+    pub difficulty: DifficultyProfile,
+}
+
+impl ArenaSettings {
+    pub fn from_cli(cli: &Cli, difficulty: DifficultyProfile) -> Self {
+        Self {
+            width: cli.width,
+            height: cli.height,
+            snake_size_start: cli.snake_size_start,
+            topology: cli.board_topology(),
+            bad_berries: cli.bad_berries,
+            difficulty,
+        }
+    }
+
+    /// Checks that `players` snakes fit where `snake_generator` lays them
+    /// out, left of the middle of the board and two rows apart, and that
+    /// neither the board nor the number of players go past the limits.
+    pub fn check(&self, players: usize) -> Result<(), String> {
+        if players == 0 {
+            return Err("a game needs at least one player".to_string());
+        }
+        if players > MAX_PLAYERS {
+            return Err(format!("a game has at most {MAX_PLAYERS} players"));
+        }
+        if self.width > MAX_BOARD_SIDE || self.height > MAX_BOARD_SIDE {
+            return Err(format!(
+                "boards are at most {MAX_BOARD_SIDE} cells wide and high"
+            ));
+        }
+        if self.snake_size_start == 0 {
+            return Err("snakes need at least one segment".to_string());
+        }
+        let rows = 2 * (players - 1);
+        if self.width / 2 < self.snake_size_start || self.height / 2 < rows || self.height == 0 {
+            return Err(format!(
+                "a {}x{} board is too small for {players} snakes",
+                self.width, self.height
+            ));
+        }
+        Ok(())
+    }
+
+    /// The command line a `World` plays these settings by.
+    fn cli(&self, players: usize) -> Cli {
+        let mut cli = Cli::parse_from(["snake"]);
+        cli.width = self.width;
+        cli.height = self.height;
+        cli.snake_size_start = self.snake_size_start;
+        cli.topology = self.topology;
+        cli.bad_berries = self.bad_berries;
+        cli.two_players_mode = players == 2;
+        cli
+    }
+}
+
+impl Default for ArenaSettings {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            height: 50,
+            snake_size_start: 3,
+            topology: Topology::Bounded,
+            bad_berries: false,
+            difficulty: DifficultyProfile::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PlayerResult {
+    pub name: String,
+    pub score: usize,
+    pub length: usize,
+    pub alive: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MatchResult {
+    pub ticks: u64,
+    /// The last snake standing, `None` when nobody survived.
+    pub winner: Option<usize>,
+    pub players: Vec<PlayerResult>,
+}
+
+impl fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.winner {
+            Some(player) => write!(f, "{} wins", self.players[player].name)?,
+            None => write!(f, "Nobody wins")?,
+        }
+        write!(f, " after {} ticks", self.ticks)?;
+        for player in self.players.iter() {
+            write!(
+                f,
+                "\n{}: score {}, length {}",
+                player.name, player.score, player.length
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Arena {
+    pub settings: ArenaSettings,
+    pub names: Vec<String>,
+    pub world: World,
+    /// Moves made so far, by any snake.
+    pub tick: u64,
+    cli: Cli,
+    schedule: Schedule,
+    forfeits: Vec<bool>,
+}
+
+impl Arena {
+    /// Lays out one snake per name like a game in the window, the first
+    /// heading east and the second west. Fails when they don't fit.
+    pub fn new(settings: ArenaSettings, names: Vec<String>, seed: u64) -> io::Result<Self> {
+        settings
+            .check(names.len())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        let cli = settings.cli(names.len());
+        let board = BoardSize {
+            width: settings.width,
+            height: settings.height,
+        };
+
+        let mut world = World::starting(settings.difficulty.clone(), Arc::new(SystemClock));
+        world.rng = StdRng::seed_from_u64(seed);
+        world.food_generator(&board, &cli);
+        snake_generator(&mut world, &board, &cli);
+
+        Ok(Self {
+            forfeits: vec![false; names.len()],
+            settings,
+            names,
+            world,
+            tick: 0,
+            cli,
+            schedule: Schedule::default(),
+        })
+    }
+
+    /// The command line the world plays by, to draw it with.
+    pub fn cli(&self) -> &Cli {
+        &self.cli
+    }
+
+    fn board(&self) -> BoardSize {
+        BoardSize {
+            width: self.settings.width,
+            height: self.settings.height,
+        }
+    }
+
+    /// Head last, like the snakes of `World`.
+    pub fn snake(&self, player: usize) -> &[(usize, usize)] {
+        match player {
+            0 => &self.world.snake,
+            _ => self.world.second_snake.as_deref().unwrap_or_default(),
+        }
+    }
+
+    pub fn score(&self, player: usize) -> usize {
+        match player {
+            0 => self.world.score,
+            _ => self.world.second_score,
+        }
+    }
+
+    /// Direction of `player`'s last move, or the spawn heading before the
+    /// first one.
+    pub fn heading(&self, player: usize) -> Direction {
+        match player {
+            0 => crate::heading(&self.world.first_snake_directions, Direction::East),
+            _ => crate::heading(&self.world.second_snake_directions, Direction::West),
+        }
+    }
+
+    /// Whether `player` is still in the game: they haven't given up, and
+    /// either the game is on or they won it.
+    pub fn alive(&self, player: usize) -> bool {
+        if self.forfeits[player] {
+            return false;
+        }
+        match self.world.result {
+            Some(result) => result.winner == Some(player),
+            None => !self.world.finished,
+        }
+    }
+
+    /// Buffers a turn for `player`, ignored once they are out.
+    pub fn turn(&mut self, player: usize, direction: Direction) {
+        if !self.alive(player) {
+            return;
+        }
+        match player {
+            0 => self.world.turn_first_snake(direction),
+            _ => self.world.turn_second_snake(direction),
+        }
+    }
+
+    /// Takes `player` out of the game, for instance when they disconnect.
+    pub fn forfeit(&mut self, player: usize) {
+        self.forfeits[player] = true;
+    }
+
+    pub fn finished(&self) -> bool {
+        self.world.finished || self.forfeits.contains(&true)
+    }
+
+    /// Which snake moves next, and at what game time since the start.
+    /// `None` once the game is over.
+    pub fn next_move(&mut self) -> Option<(usize, Duration)> {
+        if self.finished() {
+            return None;
+        }
+        self.schedule.next(&self.world.tick_intervals(&self.cli))
+    }
+
+    /// Moves the snake that is due next.
+    pub fn step(&mut self) {
+        let Some((player, at)) = self.next_move() else {
+            return;
+        };
+        self.world.tick(player, &self.board(), &self.cli);
+        self.schedule.moved(player, at);
+        self.tick += 1;
+    }
+
+    pub fn result(&self) -> MatchResult {
+        let players = self.names.len();
+        let mut alive = (0..players).filter(|player| self.alive(*player));
+        let winner = match (alive.next(), alive.next()) {
+            (Some(player), None) if players > 1 => Some(player),
+            _ => None,
+        };
+
+        MatchResult {
+            ticks: self.tick,
+            winner,
+            players: self
+                .names
+                .iter()
+                .enumerate()
+                .map(|(player, name)| PlayerResult {
+                    name: name.clone(),
+                    score: self.score(player),
+                    length: self.snake(player).len(),
+                    alive: self.alive(player),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn arena(players: usize) -> Arena {
+        let settings = ArenaSettings {
+            width: 10,
+            height: 8,
+            ..ArenaSettings::default()
+        };
+        let names = (1..=players).map(|player| format!("P{player}")).collect();
+        let mut arena = Arena::new(settings, names, 7).unwrap();
+        arena.world.food = (0, 0);
+        arena
+    }
+
+    #[test]
+    fn boards_too_small_or_too_big_are_refused() {
+        let settings = |width, height| ArenaSettings {
+            width,
+            height,
+            ..ArenaSettings::default()
+        };
+        let names = || vec!["P1".to_string(), "P2".to_string()];
+        assert!(Arena::new(settings(6, 4), names(), 1).is_ok());
+        assert!(Arena::new(settings(6, 1), vec!["P1".to_string()], 1).is_ok());
+        assert!(Arena::new(settings(0, 0), names(), 1).is_err());
+        assert!(Arena::new(settings(5, 4), names(), 1).is_err());
+        assert!(Arena::new(settings(6, 3), names(), 1).is_err());
+        assert!(Arena::new(settings(6, 0), vec!["P1".to_string()], 1).is_err());
+        assert!(Arena::new(settings(6, 4), Vec::new(), 1).is_err());
+        assert!(Arena::new(settings(MAX_BOARD_SIDE, MAX_BOARD_SIDE), names(), 1).is_ok());
+        assert!(Arena::new(settings(MAX_BOARD_SIDE + 1, 4), names(), 1).is_err());
+        assert!(Arena::new(settings(6, MAX_BOARD_SIDE + 1), names(), 1).is_err());
+        let crowd = (0..=MAX_PLAYERS).map(|player| player.to_string()).collect();
+        assert!(Arena::new(settings(20, 20), crowd, 1).is_err());
+    }
+
+    #[test]
+    fn snakes_start_like_in_the_window() {
+        let arena = arena(2);
+        assert_eq!(arena.snake(0), &[(2, 4), (3, 4), (4, 4)]);
+        assert_eq!(arena.snake(1), &[(4, 2), (3, 2), (2, 2)]);
+        assert_eq!(arena.heading(0), Direction::East);
+        assert_eq!(arena.heading(1), Direction::West);
+    }
+
+    #[test]
+    fn crashing_into_another_snake_is_fatal() {
+        let mut arena = arena(2);
+        arena.turn(1, Direction::South);
+        arena.step();
+        arena.step();
+        assert_eq!(arena.snake(1).last(), Some(&(2, 3)));
+        assert!(!arena.finished());
+
+        // The second snake's head now runs into the first snake's tail.
+        arena.step();
+        arena.step();
+        assert!(arena.finished());
+        assert!(arena.alive(0));
+        assert!(!arena.alive(1));
+        assert_eq!(arena.next_move(), None);
+
+        let result = arena.result();
+        assert_eq!(result.winner, Some(0));
+        assert_eq!(result.ticks, 4);
+        assert_eq!(result.players[1].length, 3);
+    }
+
+    #[test]
+    fn forfeits_end_the_match() {
+        let mut arena = arena(2);
+        arena.forfeit(1);
+        arena.turn(1, Direction::South);
+        assert!(arena.finished());
+        assert_eq!(arena.result().winner, Some(0));
+        assert!(arena.world.second_snake_turns.is_empty());
+
+        let mut alone = self::arena(1);
+        alone.forfeit(0);
+        assert!(alone.finished());
+        assert_eq!(alone.result().winner, None);
+    }
+
+    #[test]
+    fn walls_and_food() {
+        let mut arena = arena(1);
+        arena.world.food = (5, 4);
+        arena.turn(0, Direction::East);
+        arena.step();
+        arena.world.food = (0, 0);
+        assert_eq!(arena.snake(0).len(), 4);
+        assert_eq!(arena.score(0), 10);
+        assert_eq!(arena.world.snake_speed, 116);
+
+        while arena.alive(0) {
+            arena.step();
+        }
+        assert_eq!(arena.snake(0).last(), Some(&(9, 4)));
+        assert_eq!(arena.result().winner, None);
+
+        let settings = ArenaSettings {
+            width: 10,
+            height: 8,
+            topology: Topology::Torus,
+            ..ArenaSettings::default()
+        };
+        let mut ghost = Arena::new(settings, vec!["P1".to_string()], 7).unwrap();
+        ghost.world.food = (0, 0);
+        ghost.turn(0, Direction::East);
+        for _ in 0..6 {
+            ghost.step();
+        }
+        assert!(ghost.alive(0));
+        assert_eq!(ghost.snake(0).last(), Some(&(0, 4)));
+    }
+
+    #[test]
+    fn snakes_keep_their_own_pace() {
+        let mut arena = arena(2);
+        arena.world.second_snake_speed = 150;
+        let mut moves = Vec::new();
+        while let Some((player, at)) = arena.next_move() {
+            if at.as_millis() > 300 {
+                break;
+            }
+            moves.push((player, at.as_millis()));
+            arena.step();
+        }
+        assert_eq!(moves, vec![(0, 120), (1, 150), (0, 240), (1, 300)]);
+    }
+}
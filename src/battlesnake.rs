@@ -0,0 +1,391 @@
+//! Bots following the Battlesnake API: the game describes the board to a
+//! local HTTP server on `/start`, `/move` and `/end`, and `/move` answers
+//! with `up`, `down`, `left` or `right`. Battlesnake puts `y = 0` at the
+//! bottom of the board, so rows are flipped on the way out.
+
+use crate::bot::{parse_direction, Board, Bot};
+use crate::Direction;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use web_time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Coord {
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Ruleset {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Game {
+    pub id: String,
+    pub ruleset: Ruleset,
+    pub map: String,
+    /// Milliseconds the snake has to answer `/move`.
+    pub timeout: u64,
+    pub source: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Battlesnake {
+    pub id: String,
+    pub name: String,
+    pub health: u32,
+    /// Head first.
+    pub body: Vec<Coord>,
+    pub latency: String,
+    pub head: Coord,
+    pub length: usize,
+    pub shout: String,
+    pub squad: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct BattlesnakeBoard {
+    pub height: usize,
+    pub width: usize,
+    pub food: Vec<Coord>,
+    /// Bad berries, the closest thing we have to hazards.
+    pub hazards: Vec<Coord>,
+    pub snakes: Vec<Battlesnake>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct GameState {
+    pub game: Game,
+    pub turn: u64,
+    pub board: BattlesnakeBoard,
+    pub you: Battlesnake,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MoveResponse {
+    #[serde(rename = "move")]
+    pub direction: String,
+    #[serde(default)]
+    pub shout: String,
+}
+
+impl GameState {
+    pub fn new(board: &Board, game: &Game) -> Self {
+        let coord = |(x, y): (usize, usize)| Coord {
+            x,
+            y: board.height - 1 - y,
+        };
+        let snake = |player: usize| {
+            let snake = &board.snakes[player];
+            Battlesnake {
+                id: format!("snake-{player}"),
+                name: snake.name.clone(),
+                health: 100,
+                body: snake.body.iter().cloned().map(coord).collect(),
+                latency: "0".to_string(),
+                head: coord(snake.head),
+                length: snake.body.len(),
+                shout: String::new(),
+                squad: String::new(),
+            }
+        };
+
+        Self {
+            game: game.clone(),
+            turn: board.tick,
+            board: BattlesnakeBoard {
+                height: board.height,
+                width: board.width,
+                food: vec![coord(board.food)],
+                hazards: board.bad_berry.into_iter().map(coord).collect(),
+                snakes: (0..board.snakes.len())
+                    .filter(|player| board.snakes[*player].alive)
+                    .map(snake)
+                    .collect(),
+            },
+            you: snake(board.you),
+        }
+    }
+}
+
+/// A Battlesnake bot served over plain HTTP, for instance `http://localhost:8000`.
+pub struct HttpBot {
+    url: String,
+    host: String,
+    port: u16,
+    path: String,
+    game: Game,
+    budget: Duration,
+}
+
+impl HttpBot {
+    pub fn new(url: &str, budget: Duration) -> io::Result<Self> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{url} is not an http:// address"),
+            )
+        };
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+
+        Ok(Self {
+            url: url.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.trim_end_matches('/').to_string(),
+            game: Game {
+                id: format!("{:016x}", rand::random::<u64>()),
+                ruleset: Ruleset {
+                    name: "standard".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+                map: "standard".to_string(),
+                timeout: budget.as_millis() as u64,
+                source: "snake".to_string(),
+            },
+            budget,
+        })
+    }
+
+    /// Posts `body` to `endpoint` and returns the body of the answer, as long
+    /// as all of it comes back within the budget.
+    fn post<T: Serialize>(&self, endpoint: &str, body: &T) -> io::Result<String> {
+        let deadline = Instant::now() + self.budget;
+        let remaining = || {
+            deadline
+                .checked_duration_since(Instant::now())
+                .filter(|left| !left.is_zero())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::TimedOut))
+        };
+
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?;
+        let mut stream = TcpStream::connect_timeout(&addr, remaining()?)?;
+
+        let body = serde_json::to_vec(body)?;
+        let head = format!(
+            "POST {}{} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            endpoint,
+            self.host,
+            self.port,
+            body.len()
+        );
+        stream.set_write_timeout(Some(remaining()?))?;
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&body)?;
+
+        let mut response = Vec::new();
+        let mut chunk = [0; 4096];
+        loop {
+            stream.set_read_timeout(Some(remaining()?))?;
+            match stream.read(&mut chunk)? {
+                0 => break,
+                read => response.extend_from_slice(&chunk[..read]),
+            }
+            if let Some(body) = complete_body(&response, false)? {
+                return Ok(body);
+            }
+        }
+        complete_body(&response, true)?.ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+/// The body of an HTTP response once all of it has arrived, `closed` telling
+/// whether the server is done sending.
+fn complete_body(response: &[u8], closed: bool) -> io::Result<Option<String>> {
+    let Some(end) = response.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head = String::from_utf8_lossy(&response[..end]).to_ascii_lowercase();
+    let body = &response[end + 4..];
+
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    if !status.starts_with('2') {
+        return Err(io::Error::other(format!("bot answered with {status}")));
+    }
+
+    let header = |name: &str| {
+        head.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+    };
+
+    if header("transfer-encoding").is_some_and(|encoding| encoding.contains("chunked")) {
+        let mut decoded = Vec::new();
+        let mut rest = body;
+        loop {
+            let Some(line) = rest.windows(2).position(|window| window == b"\r\n") else {
+                return Ok(None);
+            };
+            let size = std::str::from_utf8(&rest[..line])
+                .ok()
+                .and_then(|size| usize::from_str_radix(size.split(';').next()?.trim(), 16).ok())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+            if size == 0 {
+                return Ok(Some(String::from_utf8_lossy(&decoded).into_owned()));
+            }
+            if rest.len() < line + 2 + size + 2 {
+                return Ok(None);
+            }
+            decoded.extend_from_slice(&rest[line + 2..line + 2 + size]);
+            rest = &rest[line + 2 + size + 2..];
+        }
+    }
+
+    match header("content-length").and_then(|length| length.parse::<usize>().ok()) {
+        Some(length) if body.len() >= length => {
+            Ok(Some(String::from_utf8_lossy(&body[..length]).into_owned()))
+        }
+        Some(_) => Ok(None),
+        // Without a length the body runs until the connection closes.
+        None => Ok(closed.then(|| String::from_utf8_lossy(body).into_owned())),
+    }
+}
+
+impl Bot for HttpBot {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    fn start(&mut self, board: &Board) {
+        let _ = self.post("/start", &GameState::new(board, &self.game));
+    }
+
+    fn choose(&mut self, board: &Board) -> Option<Direction> {
+        let answer = self
+            .post("/move", &GameState::new(board, &self.game))
+            .ok()?;
+        let answer: MoveResponse = serde_json::from_str(&answer).ok()?;
+        parse_direction(&answer.direction)
+    }
+
+    fn end(&mut self, board: &Board) {
+        let _ = self.post("/end", &GameState::new(board, &self.game));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::{Arena, ArenaSettings};
+    use crate::bot::DEFAULT_BUDGET;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// Answers `requests` requests like a Battlesnake server always going
+    /// `up`, and reports the path and the game state of each one.
+    fn stand_in(requests: usize, chunked: bool) -> (String, mpsc::Receiver<(String, GameState)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/snake/", listener.local_addr().unwrap());
+        let (sender, received) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).unwrap();
+
+                let path = request_line.split_whitespace().nth(1).unwrap().to_string();
+                sender
+                    .send((path, serde_json::from_slice(&body).unwrap()))
+                    .unwrap();
+
+                let answer = r#"{"move":"up","shout":"hello"}"#;
+                let response = if chunked {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{answer}\r\n0\r\n\r\n",
+                        answer.len()
+                    )
+                } else {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{answer}",
+                        answer.len()
+                    )
+                };
+                stream.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    fn board() -> Board {
+        let settings = ArenaSettings {
+            width: 11,
+            height: 11,
+            ..ArenaSettings::default()
+        };
+        let mut arena =
+            Arena::new(settings, vec!["us".to_string(), "them".to_string()], 1).unwrap();
+        arena.world.food = (5, 0);
+        Board::new(&arena, 1)
+    }
+
+    #[test]
+    fn bots_are_called_on_every_endpoint() {
+        let (url, received) = stand_in(3, false);
+        let mut bot = HttpBot::new(&url, Duration::from_secs(2)).unwrap();
+        let board = board();
+
+        bot.start(&board);
+        assert_eq!(bot.choose(&board), Some(Direction::North));
+        bot.end(&board);
+
+        let requests: Vec<(String, GameState)> = received.iter().take(3).collect();
+        let paths: Vec<&str> = requests.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["/snake/start", "/snake/move", "/snake/end"]);
+
+        let state = &requests[1].1;
+        assert_eq!(state.you.name, "them");
+        assert_eq!(state.board.snakes.len(), 2);
+        // Row 0 is at the top here, at the bottom for Battlesnake.
+        assert_eq!(state.board.food, vec![Coord { x: 5, y: 10 }]);
+        assert_eq!(state.you.head, Coord { x: 2, y: 7 });
+        assert_eq!(state.you.body.len(), state.you.length);
+        assert_eq!(state.game.timeout, 2000);
+    }
+
+    #[test]
+    fn chunked_answers_and_unreachable_bots() {
+        let (url, _received) = stand_in(1, true);
+        let mut bot = HttpBot::new(&url, Duration::from_secs(2)).unwrap();
+        assert_eq!(bot.choose(&board()), Some(Direction::North));
+
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", silent.local_addr().unwrap());
+        let mut bot = HttpBot::new(&url, Duration::from_millis(100)).unwrap();
+        assert_eq!(bot.choose(&board()), None);
+
+        assert!(HttpBot::new("https://example.com", DEFAULT_BUDGET).is_err());
+    }
+}
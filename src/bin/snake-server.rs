@@ -0,0 +1,86 @@
+//! Headless server hosting rooms of networked games, no window needed.
+
+use clap::Parser;
+use snake::{
+    arena::ArenaSettings,
+    clock::SystemClock,
+    connection,
+    server::{RoomSettings, Server},
+    topology::Topology,
+    Difficulty,
+};
+use std::sync::Arc;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Snake game server", long_about = None)]
+struct ServerCli {
+    #[arg(long, default_value_t = format!("0.0.0.0:{}", connection::DEFAULT_PORT))]
+    addr: String,
+    /// Room to open at startup, as `name:players`. Can be repeated
+    #[arg(long = "room", default_value = "lobby:2")]
+    rooms: Vec<String>,
+    /// Only serve the rooms opened at startup, clients can't create their own
+    #[arg(long, default_value_t = false)]
+    no_client_rooms: bool,
+    #[arg(long, default_value_t = 80)]
+    width: usize,
+    #[arg(long, default_value_t = 50)]
+    height: usize,
+    #[arg(long, default_value_t = 3)]
+    snake_size_start: usize,
+    #[arg(long, default_value_t = Difficulty::Medium)]
+    speed_increase: Difficulty,
+    #[arg(long, default_value_t = false)]
+    bad_berries: bool,
+    #[arg(long, default_value_t = false)]
+    ghost_mode: bool,
+    /// Which edges of the board wrap around, ghost mode wrapping them all
+    #[arg(long, default_value_t = Topology::Bounded)]
+    topology: Topology,
+}
+
+fn main() -> std::io::Result<()> {
+    let cli = ServerCli::parse();
+
+    let arena = ArenaSettings {
+        width: cli.width,
+        height: cli.height,
+        snake_size_start: cli.snake_size_start,
+        topology: if cli.ghost_mode {
+            Topology::Torus
+        } else {
+            cli.topology
+        },
+        bad_berries: cli.bad_berries,
+        difficulty: cli.speed_increase.profile(),
+    };
+
+    let mut server = Server::bind(
+        connection::with_default_port(&cli.addr),
+        Arc::new(SystemClock),
+    )?;
+    server.client_rooms = !cli.no_client_rooms;
+    for room in cli.rooms.iter() {
+        let (name, players) = match room.rsplit_once(':') {
+            Some((name, players)) => (name, players.parse().ok()),
+            None => (room.as_str(), Some(2)),
+        };
+        let Some(players) = players else {
+            eprintln!("Invalid room {room}, expected name:players");
+            std::process::exit(2);
+        };
+
+        let settings = RoomSettings {
+            name: name.to_string(),
+            players,
+            arena: arena.clone(),
+        };
+        if let Err(error) = server.create_room(settings) {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    }
+
+    println!("Serving on {}", server.local_addr()?);
+    server.run()
+}
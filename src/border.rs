@@ -0,0 +1,120 @@
+//! The shrinking arena of `--shrink-every`: every so often a ring of wall
+//! closes in around the board, flashing for a moment before it does.
+//! Whatever is caught in the wall, snakes included, is gone.
+use crate::topology::Topology;
+use crate::Direction;
+use web_time::Duration;
+
+/// How long the next ring flashes before it closes in.
+pub const SHRINK_WARNING: Duration = Duration::from_secs(2);
+
+/// The arena doesn't get narrower or shorter than this.
+pub const MIN_ARENA: usize = 5;
+
+pub const WALL_COLOUR: u32 = 0x00606060;
+pub const WARNING_COLOUR: u32 = 0x00FF3030;
+
+/// Whether `cell` is still in play with `border` rings of wall closed in
+/// around a `width` by `height` board.
+pub fn inside(cell: (usize, usize), border: usize, width: usize, height: usize) -> bool {
+    (border..width.saturating_sub(border)).contains(&cell.0)
+        && (border..height.saturating_sub(border)).contains(&cell.1)
+}
+
+/// Whether another ring can close in without making the arena too small.
+pub fn can_shrink(border: usize, width: usize, height: usize) -> bool {
+    let inner = |size: usize| size.saturating_sub(2 * (border + 1));
+    inner(width) >= MIN_ARENA && inner(height) >= MIN_ARENA
+}
+
+/// The cells of ring `index`, 0 being the outermost one.
+pub fn ring(index: usize, width: usize, height: usize) -> Vec<(usize, usize)> {
+    let (Some(right), Some(bottom)) = (width.checked_sub(index + 1), height.checked_sub(index + 1))
+    else {
+        return Vec::new();
+    };
+    if index > right || index > bottom {
+        return Vec::new();
+    }
+
+    let mut cells = Vec::new();
+    for x in index..=right {
+        cells.push((x, index));
+        if bottom != index {
+            cells.push((x, bottom));
+        }
+    }
+    for y in index + 1..bottom {
+        cells.push((index, y));
+        if right != index {
+            cells.push((right, y));
+        }
+    }
+    cells
+}
+
+/// Like `Topology::step`, with the edges of the board `border` rings in.
+pub fn step(
+    topology: Topology,
+    position: (usize, usize),
+    direction: &Direction,
+    border: usize,
+    width: usize,
+    height: usize,
+) -> Option<(usize, usize)> {
+    let inner = (
+        position.0.checked_sub(border)?,
+        position.1.checked_sub(border)?,
+    );
+    let (width, height) = (
+        width.checked_sub(2 * border)?,
+        height.checked_sub(2 * border)?,
+    );
+    if inner.0 >= width || inner.1 >= height {
+        return None;
+    }
+
+    topology
+        .step(inner, direction, width, height)
+        .map(|(x, y)| (x + border, y + border))
+}
+
+/// Whether the ring about to close in is lit, `elapsed` into the `every`
+/// between two rings. It flashes a few times a second during the warning.
+pub fn flashing(elapsed: Duration, every: Duration) -> bool {
+    let left = every.saturating_sub(elapsed);
+    left <= SHRINK_WARNING && (left.as_millis() / 250) % 2 == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rings_close_in() {
+        assert!(inside((1, 1), 1, 10, 8));
+        assert!(!inside((8, 3), 2, 10, 8));
+        assert!(can_shrink(0, 10, 8));
+        assert!(!can_shrink(1, 10, 8));
+
+        assert_eq!(
+            ring(1, 5, 4),
+            vec![(1, 1), (1, 2), (2, 1), (2, 2), (3, 1), (3, 2)]
+        );
+        assert_eq!(ring(0, 3, 3).len(), 8);
+        assert_eq!(ring(2, 3, 3), Vec::new());
+
+        let east = |x| step(Topology::Bounded, (x, 3), &Direction::East, 2, 10, 8);
+        assert_eq!(east(6), Some((7, 3)));
+        assert_eq!(east(7), None);
+        assert_eq!(
+            step(Topology::Torus, (7, 3), &Direction::East, 2, 10, 8),
+            Some((2, 3))
+        );
+
+        let every = Duration::from_secs(10);
+        assert!(!flashing(Duration::from_secs(7), every));
+        assert!(!flashing(Duration::from_millis(8100), every));
+        assert!(flashing(Duration::from_millis(8400), every));
+    }
+}
@@ -0,0 +1,509 @@
+//! Snakes driven by programs instead of keyboards. Each time its snake is due
+//! to move, a bot is shown the board and picks a direction; a bot that
+//! doesn't answer in time, or answers nonsense, either forfeits or keeps
+//! going straight.
+//!
+//! External bots are child processes: they get the `Board` as one line of
+//! JSON on stdin before each move and answer with one line on stdout, `north`,
+//! `south`, `east` or `west` (`up`, `down`, `right` and `left` work too).
+//! Answers are matched to boards in order, one line each, so an answer that
+//! comes in after its tick is over is thrown away rather than used for the
+//! next one.
+//! Their stdin is closed once the match is over. Bots can also be HTTP
+//! servers speaking the Battlesnake API, see `battlesnake`, or one of the
+//! simple AIs built in here.
+
+use crate::arena::{Arena, MatchResult};
+use crate::battlesnake::HttpBot;
+use crate::topology::Topology;
+use crate::Direction;
+use clap::ValueEnum;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::thread;
+use web_time::{Duration, Instant};
+
+/// How long a bot gets to answer by default.
+pub const DEFAULT_BUDGET: Duration = Duration::from_millis(100);
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Default, Serialize, Deserialize)]
+pub enum OnFailure {
+    /// The snake is out of the match
+    Forfeit,
+    /// The snake carries on in the direction it was heading
+    #[default]
+    KeepStraight,
+}
+
+impl fmt::Display for OnFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OnFailure::Forfeit => write!(f, "forfeit"),
+            OnFailure::KeepStraight => write!(f, "keep-straight"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SnakeView {
+    pub name: String,
+    pub head: (usize, usize),
+    /// Head first.
+    pub body: Vec<(usize, usize)>,
+    pub score: usize,
+    pub alive: bool,
+}
+
+/// What a bot is shown every tick. `y` grows going south.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Board {
+    pub tick: u64,
+    /// Index of the bot's own snake in `snakes`.
+    pub you: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Which edges of the board wrap around.
+    pub topology: Topology,
+    pub snakes: Vec<SnakeView>,
+    pub food: (usize, usize),
+    pub bad_berry: Option<(usize, usize)>,
+}
+
+impl Board {
+    pub fn new(arena: &Arena, you: usize) -> Self {
+        Self {
+            tick: arena.tick,
+            you,
+            width: arena.settings.width,
+            height: arena.settings.height,
+            topology: arena.settings.topology,
+            snakes: arena
+                .names
+                .iter()
+                .enumerate()
+                .map(|(player, name)| {
+                    let body = arena.snake(player);
+                    SnakeView {
+                        name: name.clone(),
+                        head: body[body.len() - 1],
+                        body: body.iter().rev().cloned().collect(),
+                        score: arena.score(player),
+                        alive: arena.alive(player),
+                    }
+                })
+                .collect(),
+            food: arena.world.food,
+            bad_berry: arena.world.bad_berries_position,
+        }
+    }
+}
+
+pub fn parse_direction(answer: &str) -> Option<Direction> {
+    match answer
+        .trim()
+        .trim_matches('"')
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "north" | "up" => Some(Direction::North),
+        "south" | "down" => Some(Direction::South),
+        "east" | "right" => Some(Direction::East),
+        "west" | "left" => Some(Direction::West),
+        _ => None,
+    }
+}
+
+pub trait Bot: Send {
+    fn name(&self) -> &str;
+
+    /// Called once before the first tick.
+    fn start(&mut self, _board: &Board) {}
+
+    /// The direction to take this tick, `None` when the bot failed to give
+    /// a valid one in time.
+    fn choose(&mut self, board: &Board) -> Option<Direction>;
+
+    /// Called once the match is over.
+    fn end(&mut self, _board: &Board) {}
+}
+
+/// Starts the bot described by `spec`: `builtin:greedy` or `builtin:random`,
+/// the `http://` address of a Battlesnake server, or else the command
+/// running a bot process.
+pub fn connect(spec: &str, budget: Duration) -> io::Result<Box<dyn Bot>> {
+    if let Some(builtin) = spec.strip_prefix("builtin:") {
+        match builtin {
+            "greedy" => Ok(Box::new(GreedyBot)),
+            "random" => Ok(Box::new(RandomBot::new(rand::random()))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no built-in bot called {builtin}, try greedy or random"),
+            )),
+        }
+    } else if spec.starts_with("http://") {
+        Ok(Box::new(HttpBot::new(spec, budget)?))
+    } else {
+        Ok(Box::new(ProcessBot::spawn(spec, budget)?))
+    }
+}
+
+/// Directions that don't lead straight into a wall or a snake.
+pub fn safe_moves(board: &Board) -> Vec<Direction> {
+    let head = board.snakes[board.you].head;
+
+    [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ]
+    .into_iter()
+    .filter(|direction| {
+        board
+            .topology
+            .step(head, direction, board.width, board.height)
+            .is_some_and(|cell| {
+                !board
+                    .snakes
+                    .iter()
+                    .any(|snake| snake.alive && snake.body.contains(&cell))
+            })
+    })
+    .collect()
+}
+
+/// Heads for the food by the shortest safe move.
+pub struct GreedyBot;
+
+impl Bot for GreedyBot {
+    fn name(&self) -> &str {
+        "builtin:greedy"
+    }
+
+    fn choose(&mut self, board: &Board) -> Option<Direction> {
+        let head = board.snakes[board.you].head;
+        let distance = |direction: &Direction| {
+            let (x, y) = Topology::Torus
+                .step(head, direction, board.width, board.height)
+                .unwrap_or(head);
+            x.abs_diff(board.food.0) + y.abs_diff(board.food.1)
+        };
+
+        safe_moves(board)
+            .into_iter()
+            .min_by_key(distance)
+            .or(Some(Direction::North))
+    }
+}
+
+/// Wanders around, only avoiding certain death.
+pub struct RandomBot {
+    rng: StdRng,
+}
+
+impl RandomBot {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Bot for RandomBot {
+    fn name(&self) -> &str {
+        "builtin:random"
+    }
+
+    fn choose(&mut self, board: &Board) -> Option<Direction> {
+        safe_moves(board)
+            .choose(&mut self.rng)
+            .cloned()
+            .or(Some(Direction::North))
+    }
+}
+
+/// A bot running as a child process.
+pub struct ProcessBot {
+    name: String,
+    child: Child,
+    /// Boards for the thread writing to the bot's stdin. Only one can wait
+    /// behind the one being written, so a bot that stops reading can't
+    /// hold up the match.
+    boards: Option<SyncSender<Vec<u8>>>,
+    /// Answer lines, numbered from 1 in the order they came in.
+    answers: Receiver<(u64, String)>,
+    /// Boards sent so far, the last one being the board waiting for an answer.
+    asked: u64,
+    budget: Duration,
+}
+
+impl ProcessBot {
+    /// Starts `command`, a program followed by its arguments separated by
+    /// whitespace.
+    pub fn spawn(command: &str, budget: Duration) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty bot command"))?;
+
+        let mut process = Command::new(program);
+        process.args(words);
+        Self::start(command.to_string(), process, budget)
+    }
+
+    /// Starts an already configured process.
+    pub fn start(name: String, mut command: Command, budget: Duration) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        let (sender, answers) = mpsc::channel();
+        thread::spawn(move || {
+            for (turn, line) in (1..).zip(stdout.lines()) {
+                let Ok(line) = line else { break };
+                if sender.send((turn, line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (boards, lines) = mpsc::sync_channel::<Vec<u8>>(1);
+        thread::spawn(move || {
+            for line in lines {
+                if stdin.write_all(&line).is_err() {
+                    break;
+                }
+            }
+            // Dropping stdin here closes it, telling the bot the match is over.
+        });
+
+        Ok(Self {
+            name,
+            child,
+            boards: Some(boards),
+            answers,
+            asked: 0,
+            budget,
+        })
+    }
+}
+
+impl Bot for ProcessBot {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn choose(&mut self, board: &Board) -> Option<Direction> {
+        let mut line = serde_json::to_vec(board).ok()?;
+        line.push(b'\n');
+        // With its stdin full the bot isn't keeping up, which is as good as
+        // not answering in time.
+        self.boards.as_ref()?.try_send(line).ok()?;
+        self.asked += 1;
+
+        let deadline = Instant::now() + self.budget;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.answers.recv_timeout(left) {
+                Ok((turn, answer)) if turn == self.asked => return parse_direction(&answer),
+                // Came in too late for an earlier tick, it doesn't count.
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    fn end(&mut self, _board: &Board) {
+        self.boards = None;
+    }
+}
+
+impl Drop for ProcessBot {
+    fn drop(&mut self) {
+        self.boards = None;
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A match between bots, one per snake of the arena.
+pub struct BotMatch {
+    pub arena: Arena,
+    pub bots: Vec<Box<dyn Bot>>,
+    pub on_failure: OnFailure,
+}
+
+impl BotMatch {
+    pub fn new(arena: Arena, mut bots: Vec<Box<dyn Bot>>, on_failure: OnFailure) -> Self {
+        for (player, bot) in bots.iter_mut().enumerate() {
+            bot.start(&Board::new(&arena, player));
+        }
+
+        Self {
+            arena,
+            bots,
+            on_failure,
+        }
+    }
+
+    /// Asks the bot whose snake is due next for its move, then moves it.
+    pub fn step(&mut self) {
+        let Some((player, _)) = self.arena.next_move() else {
+            return;
+        };
+
+        match (
+            self.bots[player].choose(&Board::new(&self.arena, player)),
+            self.on_failure,
+        ) {
+            (Some(direction), _) => self.arena.turn(player, direction),
+            (None, OnFailure::Forfeit) => return self.arena.forfeit(player),
+            (None, OnFailure::KeepStraight) => {
+                let heading = self.arena.heading(player);
+                self.arena.turn(player, heading);
+            }
+        }
+        self.arena.step();
+    }
+
+    pub fn finished(&self) -> bool {
+        self.arena.finished()
+    }
+
+    /// Plays until a single snake is left or `max_ticks` have gone by, and
+    /// lets the bots know it's over.
+    pub fn play(mut self, max_ticks: u64) -> MatchResult {
+        while !self.finished() && self.arena.tick < max_ticks {
+            self.step();
+        }
+        self.end()
+    }
+
+    pub fn end(mut self) -> MatchResult {
+        for (player, bot) in self.bots.iter_mut().enumerate() {
+            bot.end(&Board::new(&self.arena, player));
+        }
+        self.arena.result()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::ArenaSettings;
+
+    fn arena() -> Arena {
+        let settings = ArenaSettings {
+            width: 12,
+            height: 9,
+            ..ArenaSettings::default()
+        };
+        Arena::new(settings, vec!["first".to_string(), "second".to_string()], 3).unwrap()
+    }
+
+    fn script(script: &str) -> Box<dyn Bot> {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        Box::new(
+            ProcessBot::start(script.to_string(), command, Duration::from_millis(200)).unwrap(),
+        )
+    }
+
+    #[test]
+    fn directions_are_parsed_leniently() {
+        assert_eq!(parse_direction("north\n"), Some(Direction::North));
+        assert_eq!(parse_direction("\"LEFT\""), Some(Direction::West));
+        assert_eq!(parse_direction("Down"), Some(Direction::South));
+        assert_eq!(parse_direction("still"), None);
+        assert_eq!(parse_direction("banana"), None);
+    }
+
+    #[test]
+    fn bots_see_the_board_and_steer() {
+        let eastward = script("while read board; do echo east; done");
+        let by_seat = script(
+            r#"while read board; do case "$board" in *'"you":1'*) echo up;; *) echo RIGHT;; esac; done"#,
+        );
+        let mut game = BotMatch::new(arena(), vec![eastward, by_seat], OnFailure::Forfeit);
+        game.step();
+        game.step();
+        assert_eq!(game.arena.heading(0), Direction::East);
+        assert_eq!(game.arena.heading(1), Direction::North);
+
+        let cat = ProcessBot::spawn("cat -u", DEFAULT_BUDGET).unwrap();
+        assert_eq!(cat.name(), "cat -u");
+    }
+
+    #[test]
+    fn built_in_bots_stay_out_of_trouble() {
+        let mut arena = arena();
+        arena.world.food = (11, 4);
+        let board = Board::new(&arena, 0);
+        assert_eq!(
+            safe_moves(&board),
+            vec![Direction::North, Direction::East, Direction::South]
+        );
+        assert_eq!(GreedyBot.choose(&board), Some(Direction::East));
+
+        let bots = vec![
+            connect("builtin:greedy", DEFAULT_BUDGET).unwrap(),
+            connect("builtin:random", DEFAULT_BUDGET).unwrap(),
+        ];
+        let result = BotMatch::new(arena, bots, OnFailure::Forfeit).play(50);
+        assert!(result.ticks > 0);
+        assert!(connect("builtin:clever", DEFAULT_BUDGET).is_err());
+    }
+
+    #[test]
+    fn failing_bots_forfeit_or_keep_going() {
+        let slow = "while read board; do sleep 1; echo north; done";
+        let nonsense = "while read board; do echo banana; done";
+
+        let bots = vec![script(slow), script(nonsense)];
+        let mut game = BotMatch::new(arena(), bots, OnFailure::KeepStraight);
+        game.step();
+        game.step();
+        assert!(game.arena.alive(0) && game.arena.alive(1));
+        assert_eq!(game.arena.heading(0), Direction::East);
+        assert_eq!(game.arena.heading(1), Direction::West);
+        assert_eq!(game.arena.tick, 2);
+
+        // The first snake is due first, so its bot is the first to give up.
+        let bots = vec![script(slow), script(nonsense)];
+        let result = BotMatch::new(arena(), bots, OnFailure::Forfeit).play(10);
+        assert_eq!(result.winner, Some(1));
+        assert_eq!(result.ticks, 0);
+        assert!(!result.players[0].alive);
+    }
+
+    #[test]
+    fn late_answers_dont_count_for_the_next_tick() {
+        // Answers the first board after its budget is over, the others at once.
+        let mut bot =
+            script("read board; sleep 0.25; echo north; while read board; do echo south; done");
+        let board = Board::new(&arena(), 0);
+        assert_eq!(bot.choose(&board), None);
+        assert_eq!(bot.choose(&board), Some(Direction::South));
+    }
+
+    #[test]
+    fn bots_that_stop_reading_time_out() {
+        // A board bigger than the pipe, so writing it never finishes.
+        let mut board = Board::new(&arena(), 0);
+        board.snakes[0].body = vec![(0, 0); 20_000];
+
+        let mut bot = script("sleep 10");
+        let started = Instant::now();
+        for _ in 0..4 {
+            assert_eq!(bot.choose(&board), None);
+        }
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,170 @@
+//! JSON lines over TCP, the wire both network play and the server use.
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::thread;
+use web_time::Duration;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// Lines a peer can fall behind by before it's taken for stalled.
+pub const SEND_QUEUE: usize = 256;
+
+/// How long writing a line to a peer may take before it's taken for stalled.
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A TCP connection exchanging JSON lines. Messages are read and written on
+/// separate threads, so neither polling nor a slow peer ever blocks the game
+/// loop: a peer too far behind makes `send` fail, to be dropped like one
+/// that left.
+pub struct Connection<In> {
+    stream: TcpStream,
+    outgoing: SyncSender<Vec<u8>>,
+    incoming: Receiver<In>,
+}
+
+impl<In: DeserializeOwned + Send + 'static> Connection<In> {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream.try_clone()?;
+        let (sender, incoming) = mpsc::channel();
+        let (outgoing, lines) = mpsc::sync_channel::<Vec<u8>>(SEND_QUEUE);
+
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else { break };
+                let Ok(message) = serde_json::from_str(&line) else {
+                    break;
+                };
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        thread::spawn(move || {
+            for line in lines {
+                if writer.write_all(&line).is_err() {
+                    break;
+                }
+            }
+            // Everything sent is out, or the peer is gone. Shutting down
+            // also stops the reader thread, and lets the other side see us
+            // leave.
+            let _ = writer.shutdown(std::net::Shutdown::Both);
+        });
+
+        Ok(Self {
+            stream,
+            outgoing,
+            incoming,
+        })
+    }
+
+    /// The next newcomer waiting on a non-blocking `listener`, or `None`
+    /// once there are no more for now. A newcomer whose connection can't be
+    /// set up is dropped, and a failed accept, often a passing shortage like
+    /// running out of file descriptors, is left for the next call. Both are
+    /// logged. Only a listener that stopped working is an error.
+    pub fn accept(listener: &TcpListener) -> io::Result<Option<Self>> {
+        loop {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    match stream
+                        .set_nonblocking(false)
+                        .and_then(|()| Self::new(stream))
+                    {
+                        Ok(connection) => return Ok(Some(connection)),
+                        Err(error) => eprintln!("Dropped the connection from {addr}: {error}"),
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(error) => {
+                    eprintln!("Could not accept a connection: {error}");
+                    listener.local_addr()?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Queues `message` for the writer thread. Fails once the other side is
+    /// gone or has fallen `SEND_QUEUE` lines behind.
+    pub fn send<Out: Serialize>(&mut self, message: &Out) -> io::Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        match self.outgoing.try_send(line) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the other side isn't keeping up",
+            )),
+            Err(TrySendError::Disconnected(_)) => Err(io::ErrorKind::ConnectionAborted.into()),
+        }
+    }
+
+    /// Next message if one has arrived. Errors once the other side is gone.
+    pub fn try_receive(&mut self) -> io::Result<Option<In>> {
+        match self.incoming.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(io::ErrorKind::ConnectionAborted.into()),
+        }
+    }
+
+    /// Waits for the next message.
+    pub fn receive(&mut self) -> io::Result<In> {
+        self.incoming
+            .recv()
+            .map_err(|_| io::ErrorKind::ConnectionAborted.into())
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+}
+
+/// Adds the default port to an address given without one.
+pub fn with_default_port(addr: &str) -> String {
+    if addr.parse::<SocketAddr>().is_ok()
+        || addr
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+    {
+        addr.to_string()
+    } else {
+        format!("{addr}:{DEFAULT_PORT}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stalled_peers_are_dropped_not_waited_on() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _stalled = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut connection = Connection::<String>::new(stream).unwrap();
+
+        // The peer never reads: once the socket buffers fill up the writer
+        // thread is stuck, and the queue behind it fills up instead of `send`
+        // blocking.
+        let line = "x".repeat(64 * 1024);
+        let error = (0..10 * SEND_QUEUE)
+            .find_map(|_| connection.send(&line).err())
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn default_port() {
+        assert_eq!(with_default_port("192.168.1.20"), "192.168.1.20:7878");
+        assert_eq!(with_default_port("localhost:9000"), "localhost:9000");
+        assert_eq!(with_default_port("office-pc"), "office-pc:7878");
+    }
+}
@@ -0,0 +1,439 @@
+//! Bad berries and what they do to the snake eating them. Each berry kind
+//! lists its effects, some lasting a number of the eater's moves, others
+//! applied once. Berry kinds are set separately for single player and two
+//! players games, and can be changed with a `--berries-file` like:
+//!
+//! ```text
+//! [solo]
+//! haste = speed_up:40:refresh #FF8800
+//! [versus]
+//! mirror = reverse:30:extend #AA00FF
+//! blight = score_penalty:20 shrink:2 #880000
+//! ```
+//!
+//! Timed effects are `speed_up`, `slow_down`, `reverse` and `ghost`, followed
+//! by how many moves they last and optionally how a second dose stacks:
+//! `refresh` (the default), `extend`, `stack` or `ignore`. `shrink` and
+//! `score_penalty` are followed by how much they take away. A section
+//! replaces every default kind of its mode.
+use crate::Cli;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Effect {
+    /// Halves the time between moves.
+    SpeedUp,
+    /// Doubles the time between moves.
+    SlowDown,
+    /// Every turn goes the opposite way.
+    ReverseControls,
+    /// Goes through walls like in ghost mode.
+    Ghost,
+    /// Cuts through snakes instead of crashing into them, and wins head-on.
+    Invincible,
+    /// Food pulls towards the head.
+    Magnet,
+    /// Goes through walls and snakes without losing a life, like just after
+    /// respawning.
+    Shielded,
+    /// Points scored are worth this many times more.
+    ScoreMultiplier(usize),
+    /// Cuts this many segments off the tail, once.
+    Shrink(usize),
+    /// Takes this many points off the score, once.
+    ScorePenalty(usize),
+}
+
+impl Effect {
+    pub fn is_timed(&self) -> bool {
+        !matches!(self, Effect::Shrink(_) | Effect::ScorePenalty(_))
+    }
+
+    /// Short name shown in the HUD while the effect runs.
+    pub fn label(&self) -> String {
+        match self {
+            Effect::SpeedUp => "FAST".to_string(),
+            Effect::SlowDown => "SLOW".to_string(),
+            Effect::ReverseControls => "REV".to_string(),
+            Effect::Ghost => "GHOST".to_string(),
+            Effect::Invincible => "INV".to_string(),
+            Effect::Magnet => "MAG".to_string(),
+            Effect::Shielded => "SAFE".to_string(),
+            Effect::ScoreMultiplier(times) => format!("X{times}"),
+            Effect::Shrink(_) => "SHRINK".to_string(),
+            Effect::ScorePenalty(_) => "PENALTY".to_string(),
+        }
+    }
+}
+
+/// What a second dose of a timed effect does while the first still runs.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Stacking {
+    /// Starts the effect over for its full duration, unless more is left.
+    Refresh,
+    /// Adds the new duration to what is left.
+    Extend,
+    /// Runs both doses side by side, so speed changes compound.
+    Stack,
+    /// Keeps the running dose as it is.
+    Ignore,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct EffectSpec {
+    pub effect: Effect,
+    /// Moves the effect lasts, unused for effects applied once.
+    pub moves: u64,
+    pub stacking: Stacking,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct BerryKind {
+    pub name: String,
+    pub colour: u32,
+    pub effects: Vec<EffectSpec>,
+}
+
+/// Berry kinds for each mode, one of them picked at random whenever a bad
+/// berry appears.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct BerryConfig {
+    pub solo: Vec<BerryKind>,
+    pub versus: Vec<BerryKind>,
+}
+
+fn kind(name: &str, colour: u32, effects: &[(Effect, u64, Stacking)]) -> BerryKind {
+    BerryKind {
+        name: name.to_string(),
+        colour,
+        effects: effects
+            .iter()
+            .map(|(effect, moves, stacking)| EffectSpec {
+                effect: *effect,
+                moves: *moves,
+                stacking: *stacking,
+            })
+            .collect(),
+    }
+}
+
+impl Default for BerryConfig {
+    fn default() -> Self {
+        let haste = kind(
+            "haste",
+            0x00FF8800,
+            &[(Effect::SpeedUp, 40, Stacking::Refresh)],
+        );
+        let sludge = kind(
+            "sludge",
+            0x00885500,
+            &[(Effect::SlowDown, 40, Stacking::Extend)],
+        );
+        let blight = kind(
+            "blight",
+            0x00880000,
+            &[
+                (Effect::ScorePenalty(20), 0, Stacking::Refresh),
+                (Effect::Shrink(2), 0, Stacking::Refresh),
+            ],
+        );
+        let phantom = kind(
+            "phantom",
+            0x0088CCFF,
+            &[(Effect::Ghost, 50, Stacking::Refresh)],
+        );
+        let mirror = kind(
+            "mirror",
+            0x00AA00FF,
+            &[(Effect::ReverseControls, 30, Stacking::Refresh)],
+        );
+
+        Self {
+            solo: vec![
+                haste.clone(),
+                sludge.clone(),
+                blight.clone(),
+                phantom.clone(),
+            ],
+            versus: vec![haste, sludge, blight, phantom, mirror],
+        }
+    }
+}
+
+impl BerryConfig {
+    /// The defaults, with the kinds from `--berries-file` replacing those of
+    /// the modes it has a section for.
+    pub fn from_cli(cli: &Cli) -> io::Result<Self> {
+        let mut config = Self::default();
+        if let Some(path) = &cli.berries_file {
+            config.apply_overrides(&std::fs::read_to_string(path)?)?;
+        }
+        Ok(config)
+    }
+
+    pub fn kinds(&self, two_players: bool) -> &[BerryKind] {
+        if two_players {
+            &self.versus
+        } else {
+            &self.solo
+        }
+    }
+
+    /// Reads berry kinds in the format shown at the top of this module. Blank
+    /// lines and lines starting with `#` are ignored.
+    pub fn apply_overrides(&mut self, text: &str) -> io::Result<()> {
+        let mut section: Option<Vec<BerryKind>> = None;
+        let mut mode = "";
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |message: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{message} in berries: {line}"),
+                )
+            };
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                self.replace(mode, section.take());
+                mode = match name.trim() {
+                    "solo" => "solo",
+                    "versus" => "versus",
+                    _ => return Err(invalid("unknown mode")),
+                };
+                section = Some(Vec::new());
+                continue;
+            }
+
+            let kinds = section
+                .as_mut()
+                .ok_or_else(|| invalid("berry outside of [solo] or [versus]"))?;
+            let (name, specs) = line.split_once('=').ok_or_else(|| invalid("missing `=`"))?;
+            let mut kind = BerryKind {
+                name: name.trim().to_string(),
+                colour: 0x00FF0000,
+                effects: Vec::new(),
+            };
+
+            for word in specs.split_whitespace() {
+                if let Some(colour) = word.strip_prefix('#') {
+                    kind.colour =
+                        u32::from_str_radix(colour, 16).map_err(|_| invalid("invalid colour"))?;
+                    continue;
+                }
+                kind.effects
+                    .push(parse_effect(word).ok_or_else(|| invalid("invalid effect"))?);
+            }
+            kinds.push(kind);
+        }
+        self.replace(mode, section);
+
+        Ok(())
+    }
+
+    fn replace(&mut self, mode: &str, kinds: Option<Vec<BerryKind>>) {
+        match (mode, kinds) {
+            (_, Some(kinds)) if kinds.is_empty() => (),
+            ("solo", Some(kinds)) => self.solo = kinds,
+            ("versus", Some(kinds)) => self.versus = kinds,
+            _ => (),
+        }
+    }
+}
+
+/// Parses `name[:number][:stacking]`.
+fn parse_effect(word: &str) -> Option<EffectSpec> {
+    let mut parts = word.split(':');
+    let name = parts.next()?;
+    let number: u64 = match parts.next() {
+        Some(number) => number.parse().ok()?,
+        None => 1,
+    };
+    let stacking = match parts.next() {
+        None | Some("refresh") => Stacking::Refresh,
+        Some("extend") => Stacking::Extend,
+        Some("stack") => Stacking::Stack,
+        Some("ignore") => Stacking::Ignore,
+        Some(_) => return None,
+    };
+    let effect = match name {
+        "speed_up" => Effect::SpeedUp,
+        "slow_down" => Effect::SlowDown,
+        "reverse" => Effect::ReverseControls,
+        "ghost" => Effect::Ghost,
+        "shrink" => Effect::Shrink(number as usize),
+        "score_penalty" => Effect::ScorePenalty(number as usize),
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(EffectSpec {
+        effect,
+        moves: if effect.is_timed() { number } else { 0 },
+        stacking,
+    })
+}
+
+/// A bad berry bitten during a move, settled once the move is done.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct BerryBite {
+    pub kind: usize,
+    /// Length and score of the snake before the move.
+    pub length: usize,
+    pub score: usize,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveEffect {
+    pub effect: Effect,
+    pub moves_left: u64,
+}
+
+/// Timed effects running on one snake.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Effects {
+    pub active: Vec<ActiveEffect>,
+}
+
+impl Effects {
+    /// Starts a timed effect, following its stacking rule if it already runs.
+    pub fn start(&mut self, spec: &EffectSpec) {
+        if !spec.effect.is_timed() || spec.moves == 0 {
+            return;
+        }
+        let running = self
+            .active
+            .iter_mut()
+            .find(|active| active.effect == spec.effect);
+
+        match (running, spec.stacking) {
+            (Some(running), Stacking::Refresh) => {
+                running.moves_left = running.moves_left.max(spec.moves)
+            }
+            (Some(running), Stacking::Extend) => running.moves_left += spec.moves,
+            (Some(_), Stacking::Ignore) => (),
+            (None, _) | (Some(_), Stacking::Stack) => self.active.push(ActiveEffect {
+                effect: spec.effect,
+                moves_left: spec.moves,
+            }),
+        }
+    }
+
+    /// Counts down one move, dropping the effects that ran out.
+    pub fn tick(&mut self) {
+        self.active
+            .iter_mut()
+            .for_each(|active| active.moves_left -= 1);
+        self.active.retain(|active| active.moves_left > 0);
+    }
+
+    pub fn has(&self, effect: Effect) -> bool {
+        self.active.iter().any(|active| active.effect == effect)
+    }
+
+    /// How many times points count, all running multipliers together.
+    pub fn score_multiplier(&self) -> usize {
+        self.active
+            .iter()
+            .map(|active| match active.effect {
+                Effect::ScoreMultiplier(times) => times,
+                _ => 1,
+            })
+            .product()
+    }
+
+    /// Time between moves for a snake normally moving every `speed` ms.
+    pub fn speed(&self, speed: usize) -> usize {
+        self.active
+            .iter()
+            .fold(speed, |speed, active| match active.effect {
+                Effect::SpeedUp => (speed / 2).max(1),
+                Effect::SlowDown => speed * 2,
+                _ => speed,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use insta::assert_debug_snapshot;
+
+    #[test]
+    fn berries_file_replaces_a_mode() {
+        let mut config = BerryConfig::default();
+        config
+            .apply_overrides(
+                "# only mirrors when playing alone\n\
+                 [solo]\n\
+                 mirror = reverse:30:extend score_penalty:5 #AA00FF\n",
+            )
+            .unwrap();
+        assert_eq!(config.versus, BerryConfig::default().versus);
+        assert_debug_snapshot!(config.kinds(false), @r###"
+        [
+            BerryKind {
+                name: "mirror",
+                colour: 11141375,
+                effects: [
+                    EffectSpec {
+                        effect: ReverseControls,
+                        moves: 30,
+                        stacking: Extend,
+                    },
+                    EffectSpec {
+                        effect: ScorePenalty(
+                            5,
+                        ),
+                        moves: 0,
+                        stacking: Refresh,
+                    },
+                ],
+            },
+        ]
+        "###);
+
+        assert!(config.apply_overrides("haste = speed_up").is_err());
+        assert!(config
+            .apply_overrides("[solo]\nhaste = speed_up:x")
+            .is_err());
+        assert!(config.apply_overrides("[solo]\nhaste = fly:3").is_err());
+        assert!(config.apply_overrides("[arena]").is_err());
+    }
+
+    #[test]
+    fn doses_stack_by_their_rule() {
+        let spec = |effect, moves, stacking| EffectSpec {
+            effect,
+            moves,
+            stacking,
+        };
+        let mut effects = Effects::default();
+
+        effects.start(&spec(Effect::SpeedUp, 3, Stacking::Stack));
+        effects.start(&spec(Effect::SpeedUp, 2, Stacking::Stack));
+        effects.start(&spec(Effect::Ghost, 2, Stacking::Extend));
+        effects.start(&spec(Effect::Ghost, 2, Stacking::Extend));
+        effects.start(&spec(Effect::ReverseControls, 2, Stacking::Refresh));
+        effects.start(&spec(Effect::ReverseControls, 1, Stacking::Refresh));
+        effects.start(&spec(Effect::Shrink(2), 0, Stacking::Refresh));
+        assert_eq!(effects.speed(100), 25);
+
+        effects.tick();
+        effects.tick();
+        assert_eq!(effects.speed(100), 50);
+        assert!(effects.has(Effect::Ghost));
+        assert!(!effects.has(Effect::ReverseControls));
+
+        effects.tick();
+        assert_eq!(effects.speed(100), 100);
+        effects.tick();
+        assert_eq!(effects, Effects::default());
+    }
+}
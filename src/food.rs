@@ -0,0 +1,217 @@
+//! Food items on the board besides the main food. With `--foods N` there are
+//! up to N items at once, each worth one to three times a normal food, and
+//! with `--food-spawn-ticks` they show up over time instead of being replaced
+//! as soon as they are eaten.
+//!
+//! With `--food-lifetime` food doesn't stay forever: it blinks for its last
+//! few moves, then moves elsewhere or, for items and with `--food-vanishes`,
+//! goes away. With `--runaway-food` some items run away from the snakes.
+use crate::{rgb, Cli};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Moves before expiring during which food blinks.
+pub const BLINK_TICKS: u64 = 10;
+
+/// One food item out of this many runs away, with `--runaway-food`.
+pub const RUNAWAY_CHANCE: u32 = 3;
+
+/// Moves between two steps of a runaway item.
+pub const RUNAWAY_TICKS: u64 = 3;
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FoodItem {
+    pub position: (usize, usize),
+    /// How many normal foods this item is worth.
+    pub multiplier: usize,
+    /// Moves of the first snake since the item showed up where it is.
+    pub age: u64,
+    /// Whether the item runs away from the closest head.
+    pub runaway: bool,
+}
+
+impl FoodItem {
+    /// Rolls the worth of a new item: the bigger, the rarer.
+    pub fn new(position: (usize, usize), rng: &mut impl Rng) -> Self {
+        let multiplier = match rng.gen_range(0..10) {
+            0 => 3,
+            1 | 2 => 2,
+            _ => 1,
+        };
+        Self {
+            position,
+            multiplier,
+            age: 0,
+            runaway: false,
+        }
+    }
+
+    /// Items worth more than a normal food stand out from `food_colour`,
+    /// and runaways from all of them.
+    pub fn colour(&self, food_colour: u32) -> u32 {
+        if self.runaway {
+            return rgb(u8::MAX, 0x80, 0);
+        }
+        match self.multiplier {
+            0 | 1 => food_colour,
+            2 => rgb(u8::MAX, u8::MAX, 0),
+            _ => rgb(0, u8::MAX, u8::MAX),
+        }
+    }
+}
+
+/// How the items besides the main food come and go.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FoodSettings {
+    /// Items on the board at most, the main food included.
+    pub count: usize,
+    /// Moves of the first snake between two new items, `None` meaning an
+    /// item is replaced as soon as it's eaten.
+    pub spawn_ticks: Option<u64>,
+    /// Moves food stays where it is before expiring, if it ever does.
+    pub lifetime: Option<u64>,
+    /// Whether expired items go away instead of moving elsewhere. The main
+    /// food always moves.
+    pub vanishes: bool,
+    pub runaway: bool,
+}
+
+impl Default for FoodSettings {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            spawn_ticks: None,
+            lifetime: None,
+            vanishes: false,
+            runaway: false,
+        }
+    }
+}
+
+impl FoodSettings {
+    pub fn from_cli(cli: &Cli) -> Self {
+        Self {
+            count: cli.foods.max(1),
+            spawn_ticks: cli.food_spawn_ticks.filter(|ticks| *ticks > 0),
+            lifetime: cli.food_lifetime.filter(|ticks| *ticks > 0),
+            vanishes: cli.food_vanishes,
+            runaway: cli.runaway_food,
+        }
+    }
+
+    /// Items wanted besides the main food.
+    pub fn extra(&self) -> usize {
+        self.count.saturating_sub(1)
+    }
+
+    /// Whether an item is due after `ticks` moves since the last one.
+    pub fn spawn_due(&self, ticks: u64) -> bool {
+        self.spawn_ticks.is_some_and(|every| ticks >= every)
+    }
+
+    /// Whether food that has been there for `age` moves has to go.
+    pub fn expired(&self, age: u64) -> bool {
+        self.lifetime.is_some_and(|lifetime| age >= lifetime)
+    }
+
+    /// Whether food that has been there for `age` moves is drawn, which it
+    /// isn't every other move while it blinks.
+    pub fn visible(&self, age: u64) -> bool {
+        match self.lifetime {
+            Some(lifetime) if lifetime.saturating_sub(age) <= BLINK_TICKS => {
+                lifetime.saturating_sub(age) % 2 == 0
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Cells a runaway at `position` can step to, to get away from `head`, best
+/// first: away along the way the head is closest, then along the other. The
+/// cells are on a `width` by `height` board but may be taken.
+pub fn flee(
+    position: (usize, usize),
+    head: (usize, usize),
+    width: usize,
+    height: usize,
+) -> Vec<(usize, usize)> {
+    let away = |from: usize, to: usize, size: usize| -> Vec<usize> {
+        let mut cells = Vec::new();
+        if from >= to && from + 1 < size {
+            cells.push(from + 1);
+        }
+        if from <= to && from > 0 {
+            cells.push(from - 1);
+        }
+        cells
+    };
+    let horizontal = away(position.0, head.0, width)
+        .into_iter()
+        .map(|x| (x, position.1));
+    let vertical = away(position.1, head.1, height)
+        .into_iter()
+        .map(|y| (position.0, y));
+
+    if position.0.abs_diff(head.0) <= position.1.abs_diff(head.1) {
+        horizontal.chain(vertical).collect()
+    } else {
+        vertical.chain(horizontal).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn items_are_worth_more_the_rarer_they_are() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut counts = [0; 4];
+        for _ in 0..1000 {
+            counts[FoodItem::new((0, 0), &mut rng).multiplier] += 1;
+        }
+        assert_eq!(counts[0], 0);
+        assert!(counts[1] > counts[2] && counts[2] > counts[3] && counts[3] > 0);
+
+        let mut item = FoodItem {
+            position: (0, 0),
+            multiplier: 2,
+            age: 0,
+            runaway: false,
+        };
+        assert_eq!(item.colour(0x0000FF00), 0x00FFFF00);
+        item.runaway = true;
+        assert_eq!(item.colour(0x0000FF00), 0x00FF8000);
+
+        let cli = Cli::parse_from(["snake", "--foods", "4", "--food-spawn-ticks", "20"]);
+        let settings = FoodSettings::from_cli(&cli);
+        assert_eq!(settings.extra(), 3);
+        assert!(!settings.spawn_due(19));
+        assert!(settings.spawn_due(20));
+        assert!(!FoodSettings::default().spawn_due(1000));
+    }
+
+    #[test]
+    fn food_blinks_then_expires() {
+        let cli = Cli::parse_from(["snake", "--food-lifetime", "30"]);
+        let settings = FoodSettings::from_cli(&cli);
+        let shown: String = (15..=30)
+            .map(|age| if settings.visible(age) { '#' } else { '.' })
+            .collect();
+        assert_eq!(shown, "######.#.#.#.#.#");
+        assert!(!settings.expired(29));
+        assert!(settings.expired(30));
+        assert!(FoodSettings::default().visible(1000));
+        assert!(!FoodSettings::default().expired(1000));
+    }
+
+    #[test]
+    fn runaways_step_away_from_the_head() {
+        assert_eq!(flee((5, 5), (2, 4), 10, 10), vec![(5, 6), (6, 5)]);
+        assert_eq!(flee((5, 5), (5, 2), 10, 10), vec![(6, 5), (4, 5), (5, 6)]);
+        // Cornered, it can only go along the wall.
+        assert_eq!(flee((9, 9), (8, 9), 10, 10), vec![(9, 8)]);
+    }
+}
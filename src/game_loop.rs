@@ -0,0 +1,242 @@
+use web_time::{Duration, Instant};
+
+/// Render at most this often, whatever the simulation speed.
+pub const DEFAULT_FRAME_INTERVAL: Duration = Duration::from_millis(1000 / 60);
+
+/// How many late ticks a player is allowed to catch up on in one go. Anything
+/// older is dropped so a stalled window doesn't fast-forward the game.
+pub const MAX_CATCH_UP_TICKS: u32 = 3;
+
+/// Fixed-timestep driver: every player accumulates real time and ticks each
+/// time a whole interval has built up, independently of how often we render.
+///
+/// All methods take the current time as a parameter so the loop can be
+/// driven by any clock.
+pub struct GameLoop {
+    frame_interval: Duration,
+    accumulators: Vec<Duration>,
+    last_elapse: Instant,
+    last_render: Option<Instant>,
+}
+
+impl GameLoop {
+    pub fn new(players: usize, frame_interval: Duration, now: Instant) -> Self {
+        Self {
+            frame_interval,
+            accumulators: vec![Duration::ZERO; players],
+            last_elapse: now,
+            last_render: None,
+        }
+    }
+
+    /// Adds the time since the previous call to every player.
+    pub fn elapse(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_elapse);
+        self.last_elapse = now;
+
+        for accumulator in self.accumulators.iter_mut() {
+            *accumulator += elapsed;
+        }
+    }
+
+    /// Returns the player whose tick is the most overdue and consumes one of
+    /// its intervals. `intervals` holds each player's current tick interval,
+    /// `None` for a player that isn't moving right now, and should be
+    /// recomputed between calls so speed changes apply to the very next tick.
+    pub fn next_due(&mut self, intervals: &[Option<Duration>]) -> Option<usize> {
+        let mut due: Option<(usize, Duration)> = None;
+
+        for (player, accumulator) in self.accumulators.iter_mut().enumerate() {
+            let Some(interval) = intervals.get(player).copied().flatten() else {
+                *accumulator = Duration::ZERO;
+                continue;
+            };
+
+            let limit = interval * MAX_CATCH_UP_TICKS;
+            if *accumulator > limit {
+                *accumulator = limit;
+            }
+
+            if *accumulator >= interval {
+                let late_by = *accumulator - interval;
+                if due.is_none_or(|(_, most_late)| late_by > most_late) {
+                    due = Some((player, late_by));
+                }
+            }
+        }
+
+        let (player, _) = due?;
+        self.accumulators[player] -= intervals[player].unwrap_or_default();
+        Some(player)
+    }
+
+    /// Whether a frame should be drawn now. Records the render when it is.
+    pub fn should_render(&mut self, now: Instant) -> bool {
+        let render = self
+            .last_render
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.frame_interval);
+
+        if render {
+            self.last_render = Some(now);
+        }
+        render
+    }
+
+    /// How long the caller can sleep before either a player is due or the
+    /// next frame should be rendered.
+    pub fn time_until_next(&self, now: Instant, intervals: &[Option<Duration>]) -> Duration {
+        let until_render = match self.last_render {
+            Some(last) => (last + self.frame_interval).saturating_duration_since(now),
+            None => Duration::ZERO,
+        };
+        let pending = now.saturating_duration_since(self.last_elapse);
+
+        self.accumulators
+            .iter()
+            .zip(intervals)
+            .filter_map(|(accumulator, interval)| {
+                interval.map(|interval| interval.saturating_sub(*accumulator + pending))
+            })
+            .fold(until_render, Duration::min)
+    }
+}
+
+/// Turn order on a game clock rather than the local one, for games that
+/// have to play out the same wherever they run, like lockstep games and
+/// server rooms. The caller decides when each game time comes.
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    /// Game time of the last tick.
+    time: Duration,
+    /// Game time each player moves at next, while it is moving.
+    next_moves: Vec<Option<Duration>>,
+}
+
+impl Schedule {
+    /// Game time of the last tick since the game started.
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// Which player moves next, and when. A player that starts moving is
+    /// scheduled one of its `intervals` after the last tick; the first one
+    /// due goes, the lowest numbered on a tie. `None` while nobody moves.
+    pub fn next(&mut self, intervals: &[Option<Duration>]) -> Option<(usize, Duration)> {
+        self.next_moves.resize(intervals.len(), None);
+        for (next_move, interval) in self.next_moves.iter_mut().zip(intervals) {
+            *next_move = interval.map(|interval| next_move.unwrap_or(self.time + interval));
+        }
+
+        self.next_moves
+            .iter()
+            .enumerate()
+            .filter_map(|(player, at)| at.map(|at| (player, at)))
+            .min_by_key(|(_, at)| *at)
+    }
+
+    /// Records that `player` moved at game time `at`, as given by `next`.
+    pub fn moved(&mut self, player: usize, at: Duration) {
+        self.time = at;
+        self.next_moves[player] = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn players_tick_at_their_own_rate() {
+        let start = Instant::now();
+        let mut game_loop = GameLoop::new(2, 16 * MS, start);
+        let intervals = [Some(100 * MS), Some(150 * MS)];
+
+        let mut ticks = Vec::new();
+        for step in 1..=30 {
+            game_loop.elapse(start + step * 10 * MS);
+            while let Some(player) = game_loop.next_due(&intervals) {
+                ticks.push((step * 10, player));
+            }
+        }
+
+        assert_eq!(
+            ticks,
+            vec![(100, 0), (150, 1), (200, 0), (300, 0), (300, 1)]
+        );
+    }
+
+    #[test]
+    fn stalls_only_catch_up_a_few_ticks() {
+        let start = Instant::now();
+        let mut game_loop = GameLoop::new(1, 16 * MS, start);
+        let intervals = [Some(100 * MS)];
+
+        game_loop.elapse(start + 10_000 * MS);
+        let mut ticks = 0;
+        while game_loop.next_due(&intervals).is_some() {
+            ticks += 1;
+        }
+        assert_eq!(ticks, MAX_CATCH_UP_TICKS);
+    }
+
+    #[test]
+    fn stopped_players_do_not_accumulate() {
+        let start = Instant::now();
+        let mut game_loop = GameLoop::new(1, 16 * MS, start);
+
+        game_loop.elapse(start + 500 * MS);
+        assert_eq!(game_loop.next_due(&[None]), None);
+
+        game_loop.elapse(start + 550 * MS);
+        assert_eq!(game_loop.next_due(&[Some(100 * MS)]), None);
+        assert_eq!(
+            game_loop.time_until_next(start + 550 * MS, &[Some(100 * MS)]),
+            Duration::ZERO
+        );
+        game_loop.should_render(start + 550 * MS);
+        assert_eq!(
+            game_loop.time_until_next(start + 550 * MS, &[Some(100 * MS)]),
+            16 * MS
+        );
+        assert_eq!(
+            game_loop.time_until_next(start + 560 * MS, &[Some(100 * MS)]),
+            6 * MS
+        );
+    }
+
+    #[test]
+    fn schedules_follow_game_time() {
+        let mut schedule = Schedule::default();
+        let intervals = [Some(100 * MS), Some(150 * MS)];
+
+        let mut ticks = Vec::new();
+        while let Some((player, at)) = schedule.next(&intervals) {
+            if at > 300 * MS {
+                break;
+            }
+            schedule.moved(player, at);
+            ticks.push((at.as_millis(), player));
+        }
+        assert_eq!(
+            ticks,
+            vec![(100, 0), (150, 1), (200, 0), (300, 0), (300, 1)]
+        );
+
+        // A player that stops and starts again waits a whole interval.
+        assert_eq!(schedule.next(&[None, Some(150 * MS)]), Some((1, 450 * MS)));
+        assert_eq!(schedule.next(&[Some(100 * MS), None]), Some((0, 400 * MS)));
+        assert_eq!(schedule.next(&[None, None]), None);
+    }
+
+    #[test]
+    fn renders_are_capped() {
+        let start = Instant::now();
+        let mut game_loop = GameLoop::new(1, 16 * MS, start);
+
+        assert!(game_loop.should_render(start));
+        assert!(!game_loop.should_render(start + 10 * MS));
+        assert!(game_loop.should_render(start + 16 * MS));
+    }
+}
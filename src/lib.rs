@@ -1,11 +1,51 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use web_time::{Duration, Instant};
+
+pub mod arena;
+pub mod battlesnake;
+pub mod border;
+pub mod bot;
+pub mod clock;
+pub mod connection;
+pub mod effects;
+pub mod food;
+pub mod game_loop;
+pub mod lockstep;
+pub mod net;
+pub mod portals;
+pub mod power_ups;
+#[cfg(feature = "gui")]
+pub mod series;
+pub mod server;
+#[cfg(feature = "gui")]
+pub mod text;
+pub mod topology;
+pub mod tournament;
+
 use crate::Direction::Still;
-use clap::{Parser, ValueEnum};
+use clock::{Clock, SystemClock};
+use effects::{BerryBite, BerryConfig, Effect, EffectSpec, Effects, Stacking};
+use food::{FoodItem, FoodSettings};
+use game_loop::GameLoop;
+use portals::Portal;
+use power_ups::{PowerUp, POWER_UP_CHANCE};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::borrow::Cow;
+use std::sync::Arc;
+use topology::Topology;
+
+// Drawing and reading the keyboard only come with the `gui` feature.
+#[cfg(feature = "gui")]
 use graphic::Graphic;
-use rand::Rng;
-use std::fmt;
+#[cfg(feature = "gui")]
 use std::fs::File;
+#[cfg(feature = "gui")]
 use std::io::Write;
-use web_time::{Duration, Instant};
+#[cfg(feature = "gui")]
 use window_rs::WindowBuffer;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
@@ -26,6 +66,192 @@ impl fmt::Display for Difficulty {
     }
 }
 
+impl Difficulty {
+    pub fn profile(&self) -> DifficultyProfile {
+        match self {
+            Difficulty::Easy => DifficultyProfile {
+                name: self.to_string(),
+                starting_speed: 150,
+                speed_up_per_food: 2,
+                minimum_speed: 90,
+                bad_berries_every: 4,
+                score_multiplier: 1,
+            },
+            Difficulty::Medium => DifficultyProfile {
+                name: self.to_string(),
+                starting_speed: 120,
+                speed_up_per_food: 4,
+                minimum_speed: 60,
+                bad_berries_every: 2,
+                score_multiplier: 1,
+            },
+            Difficulty::Hard => DifficultyProfile {
+                name: self.to_string(),
+                starting_speed: 100,
+                speed_up_per_food: 6,
+                minimum_speed: 40,
+                bad_berries_every: 1,
+                score_multiplier: 3,
+            },
+        }
+    }
+}
+
+//DIFFICULTY PROFILES
+/// Points a food is worth before the difficulty's multiplier is applied.
+pub const FOOD_SCORE: usize = 10;
+
+/// Tuning for a difficulty level. Speeds are tick intervals in milliseconds,
+/// so a lower number means a faster snake.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DifficultyProfile {
+    pub name: String,
+    pub starting_speed: usize,
+    /// How many milliseconds are taken off the tick interval per food eaten.
+    pub speed_up_per_food: usize,
+    /// The tick interval never goes below this.
+    pub minimum_speed: usize,
+    /// A bad berry comes with one food out of this many, 0 meaning never.
+    pub bad_berries_every: usize,
+    pub score_multiplier: usize,
+}
+
+impl Default for DifficultyProfile {
+    fn default() -> Self {
+        Difficulty::default().profile()
+    }
+}
+
+impl DifficultyProfile {
+    /// Builds the profile selected by `--speed-increase`, with the overrides
+    /// from `--difficulty-file` and `--snake-speed` applied on top.
+    pub fn from_cli(cli: &Cli) -> std::io::Result<Self> {
+        let mut profile = cli.speed_increase.profile();
+
+        if let Some(path) = &cli.difficulty_file {
+            profile.apply_overrides(&std::fs::read_to_string(path)?)?;
+        }
+        if let Some(speed) = cli.snake_speed {
+            profile.starting_speed = speed;
+        }
+
+        Ok(profile)
+    }
+
+    /// Reads `key = value` lines over the current values. Blank lines and
+    /// lines starting with `#` are ignored.
+    pub fn apply_overrides(&mut self, text: &str) -> std::io::Result<()> {
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |message: &str| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{message} in difficulty profile: {line}"),
+                )
+            };
+            let (key, value) = line.split_once('=').ok_or_else(|| invalid("missing `=`"))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            if key == "name" {
+                self.name = value.to_string();
+                continue;
+            }
+
+            let number: usize = value.parse().map_err(|_| invalid("invalid number"))?;
+            match key {
+                "starting_speed" => self.starting_speed = number,
+                "speed_up_per_food" => self.speed_up_per_food = number,
+                "minimum_speed" => self.minimum_speed = number,
+                "bad_berries_every" => self.bad_berries_every = number,
+                "score_multiplier" => self.score_multiplier = number,
+                _ => return Err(invalid("unknown key")),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn speed_after_food(&self, speed: usize) -> usize {
+        speed
+            .saturating_sub(self.speed_up_per_food)
+            .max(self.minimum_speed)
+    }
+
+    pub fn food_score(&self) -> usize {
+        FOOD_SCORE * self.score_multiplier
+    }
+
+    /// Whether the `count`th food spawned (starting from 0) comes with a bad berry.
+    pub fn spawns_bad_berry(&self, count: usize) -> bool {
+        self.bad_berries_every != 0 && count % self.bad_berries_every == 0
+    }
+}
+//DIFFICULTY PROFILES END
+
+//HANDICAPS
+/// Per player tuning, to balance a two players game between players of
+/// different levels.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handicap {
+    /// Starting tick interval in milliseconds, instead of the difficulty's.
+    pub speed: Option<usize>,
+    /// Starting length, instead of `--snake-size-start`.
+    pub length: Option<usize>,
+    /// Percentage of the difficulty's speed up per food this player gets.
+    pub acceleration: usize,
+    /// Crashes survived by respawning near the starting position, on the
+    /// closest free rows. The snake waits there a few moves standing still
+    /// in its spawn heading, then sets off shielded for a while.
+    pub extra_lives: usize,
+}
+
+impl Default for Handicap {
+    fn default() -> Self {
+        Self {
+            speed: None,
+            length: None,
+            acceleration: 100,
+            extra_lives: 0,
+        }
+    }
+}
+
+impl Handicap {
+    pub fn speed_after_food(&self, difficulty: &DifficultyProfile, speed: usize) -> usize {
+        let speed_up = difficulty.speed_up_per_food * self.acceleration / 100;
+        speed.saturating_sub(speed_up).max(difficulty.minimum_speed)
+    }
+}
+
+/// Parses a handicap like `speed=150,length=5,acceleration=50,lives=1`, any
+/// key left out keeping its default.
+pub fn parse_handicap(text: &str) -> Result<Handicap, String> {
+    let mut handicap = Handicap::default();
+
+    for setting in text.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("missing `=` in handicap: {setting}"))?;
+        let number: usize = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid number in handicap: {setting}"))?;
+        match key.trim() {
+            "speed" => handicap.speed = Some(number),
+            "length" => handicap.length = Some(number.max(1)),
+            "acceleration" => handicap.acceleration = number,
+            "lives" => handicap.extra_lives = number,
+            _ => return Err(format!("unknown key in handicap: {setting}")),
+        }
+    }
+
+    Ok(handicap)
+}
+//HANDICAPS END
+
 #[derive(PartialEq)]
 pub enum TimeCycle {
     Forward,
@@ -33,8 +259,137 @@ pub enum TimeCycle {
     Pause,
 }
 
+//LIVES
+/// Own moves a snake that lost a life waits before moving again.
+pub const RESPAWN_DELAY: u64 = 10;
+
+/// Moves a snake is shielded for once it moves again after losing a life.
+pub const RESPAWN_SHIELD: u64 = 20;
+//LIVES END
+
+//TRON
+/// Starting tick interval of light cycles, unless `--snake-speed` says
+/// otherwise.
+pub const TRON_SPEED: usize = 60;
+//TRON END
+
+//PAUSE
+/// How long the countdown shown before the game resumes lasts.
+pub const RESUME_COUNTDOWN: Duration = Duration::from_secs(3);
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PauseState {
+    Running,
+    Paused,
+    /// Counting down, play resumes at the given instant.
+    Resuming(Instant),
+}
+//PAUSE END
+
+//VERSUS
+/// Why a two players game came to an end.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum EndReason {
+    /// Both heads met, the longer snake wins.
+    HeadOn,
+    /// The loser ran into the other snake's body.
+    HitOpponent,
+    HitSelf,
+    HitWall,
+}
+
+/// How a two players game ended. Players are numbered from 0.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct VersusResult {
+    /// `None` on a draw.
+    pub winner: Option<usize>,
+    pub reason: EndReason,
+    pub lengths: [usize; 2],
+}
+
+impl fmt::Display for VersusResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.reason {
+            EndReason::HeadOn => "head-on collision",
+            EndReason::HitOpponent => "crash into the other snake",
+            EndReason::HitSelf => "crash into itself",
+            EndReason::HitWall => "crash into a wall",
+        };
+        match self.winner {
+            Some(player) => write!(f, "Player {} wins", player + 1)?,
+            None => write!(f, "Draw")?,
+        }
+        write!(
+            f,
+            " by {reason}, lengths {} and {}",
+            self.lengths[0], self.lengths[1]
+        )
+    }
+}
+
+/// Settles a two players game from where the heads are: meeting heads go to
+/// the longer snake, a head inside a body loses. `crashed` is the player the
+/// game just stopped for, if it stopped without any of that, which can only
+/// be a wall.
+pub fn versus_result(
+    first_snake: &[(usize, usize)],
+    second_snake: &[(usize, usize)],
+    crashed: Option<usize>,
+) -> Option<VersusResult> {
+    let snakes = [first_snake, second_snake];
+    let lengths = [first_snake.len(), second_snake.len()];
+    let heads = [snakes[0][lengths[0] - 1], snakes[1][lengths[1] - 1]];
+    let hit = |player: usize| {
+        if snakes[player][..lengths[player] - 1].contains(&heads[player]) {
+            Some(EndReason::HitSelf)
+        } else if snakes[1 - player][..lengths[1 - player] - 1].contains(&heads[player]) {
+            Some(EndReason::HitOpponent)
+        } else {
+            None
+        }
+    };
+
+    let (winner, reason) = if heads[0] == heads[1] {
+        let winner = match lengths[0].cmp(&lengths[1]) {
+            std::cmp::Ordering::Greater => Some(0),
+            std::cmp::Ordering::Less => Some(1),
+            std::cmp::Ordering::Equal => None,
+        };
+        (winner, EndReason::HeadOn)
+    } else {
+        match (hit(0), hit(1), crashed) {
+            (Some(reason), Some(_), _) => (None, reason),
+            (Some(reason), None, _) => (Some(1), reason),
+            (None, Some(reason), _) => (Some(0), reason),
+            (None, None, Some(player)) => (Some(1 - player), EndReason::HitWall),
+            (None, None, None) => return None,
+        }
+    };
+
+    Some(VersusResult {
+        winner,
+        reason,
+        lengths,
+    })
+}
+
+/// Cuts `snake` (head last) where its body, the head left out, goes through
+/// `cell`, dropping that segment and everything behind it. Returns how many
+/// segments came off.
+pub fn cut_tail(snake: &mut Vec<(usize, usize)>, cell: (usize, usize)) -> usize {
+    let body = snake.len().saturating_sub(1);
+    match snake[..body].iter().position(|segment| *segment == cell) {
+        Some(index) => {
+            snake.drain(..=index);
+            index + 1
+        }
+        None => 0,
+    }
+}
+//VERSUS END
+
 //CLI
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
     /// Optional name to operate on
@@ -46,16 +401,189 @@ pub struct Cli {
     pub snake_size_start: usize,
     #[arg(long)]
     pub file_path: Option<String>,
-    #[arg(long, default_value_t = 120)]
-    pub snake_speed: usize,
+    /// Starting tick interval in milliseconds, overriding the difficulty's
+    #[arg(long)]
+    pub snake_speed: Option<usize>,
     #[arg(long, default_value_t = Difficulty::Medium)]
     pub speed_increase: Difficulty,
+    /// File of `key = value` lines customising the selected difficulty
+    #[arg(long)]
+    pub difficulty_file: Option<String>,
     #[arg(long, default_value_t = false)]
     pub bad_berries: bool,
+    /// File of berry kinds and their effects, for each mode
+    #[arg(long)]
+    pub berries_file: Option<String>,
+    /// Food items on the board at once
+    #[arg(long, default_value_t = 1)]
+    pub foods: usize,
+    /// Add a food item every this many moves instead of when one is eaten
+    #[arg(long)]
+    pub food_spawn_ticks: Option<u64>,
+    /// Food moves elsewhere after staying this many moves, blinking before
+    #[arg(long)]
+    pub food_lifetime: Option<u64>,
+    /// Expired food items go away instead of moving elsewhere
+    #[arg(long, default_value_t = false)]
+    pub food_vanishes: bool,
+    /// Some food items run away from the snakes
+    #[arg(long, default_value_t = false)]
+    pub runaway_food: bool,
+    /// Pair of portal cells like `3,4:20,10`, can be given several times
+    #[arg(long = "portal", value_parser = portals::parse_portal)]
+    pub portals: Vec<Portal>,
+    /// Portal pairs put on random free cells
+    #[arg(long, default_value_t = 0)]
+    pub random_portals: usize,
+    /// Power-ups show up with some of the food
+    #[arg(long, default_value_t = false)]
+    pub power_ups: bool,
     #[arg(long, default_value_t = false)]
     pub ghost_mode: bool,
+    /// Biting a body cuts it instead of ending the game, the other snake's
+    /// tail going to the biter
+    #[arg(long, default_value_t = false)]
+    pub tail_biting: bool,
+    /// Light cycles: no food, every move lengthens the trail and the last
+    /// one riding wins
+    #[arg(long, default_value_t = false)]
+    pub tron: bool,
+    /// Which edges of the board wrap around, ghost mode wrapping them all
+    #[arg(long, default_value_t = Topology::Bounded)]
+    pub topology: Topology,
+    /// Close the board in by a ring of wall every this many seconds, those
+    /// caught in it dying
+    #[arg(long)]
+    pub shrink_every: Option<u64>,
     #[arg(long, default_value_t = false)]
     pub two_players_mode: bool,
+    /// Show the scores over the board
+    #[arg(long, default_value_t = false)]
+    pub hud: bool,
+    /// Play two players rounds until someone has won most of N of them
+    #[arg(long)]
+    pub best_of: Option<usize>,
+    /// Extra lives of each player, on top of those of their handicap
+    #[arg(long, default_value_t = 0)]
+    pub lives: usize,
+    /// Handicap of the first player, like `speed=150,length=5,acceleration=50,lives=1`
+    #[arg(long, value_parser = parse_handicap)]
+    pub first_handicap: Option<Handicap>,
+    /// Handicap of the second player, in the same form as `--first-handicap`
+    #[arg(long, value_parser = parse_handicap)]
+    pub second_handicap: Option<Handicap>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// How the edges of the board are joined.
+    pub fn board_topology(&self) -> Topology {
+        if self.ghost_mode {
+            Topology::Torus
+        } else {
+            self.topology
+        }
+    }
+
+    /// Game time between two rings of wall closing in, if they do.
+    pub fn shrink_interval(&self) -> Option<Duration> {
+        self.shrink_every
+            .filter(|seconds| *seconds > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Handicap of `player`, counting from 0.
+    pub fn handicap(&self, player: usize) -> Handicap {
+        let handicap = if player == 0 {
+            &self.first_handicap
+        } else {
+            &self.second_handicap
+        };
+        handicap.clone().unwrap_or_default()
+    }
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Host a two players game on the network, playing the first snake
+    Host {
+        #[arg(long, default_value_t = format!("0.0.0.0:{}", connection::DEFAULT_PORT))]
+        addr: String,
+        /// Both computers run the game and only exchange inputs
+        #[arg(long, default_value_t = false)]
+        lockstep: bool,
+    },
+    /// Watch a game hosted on another computer, or a room of a snake server
+    Watch {
+        addr: String,
+        #[arg(long)]
+        room: Option<String>,
+    },
+    /// Join a game hosted on another computer
+    Join {
+        addr: String,
+        /// Must match the host's choice
+        #[arg(long, default_value_t = false)]
+        lockstep: bool,
+    },
+    /// Pit bots against each other, each one a program reading the board as
+    /// JSON lines on stdin and answering with a direction on stdout, or a
+    /// Battlesnake HTTP server
+    Bots {
+        /// Command starting a bot, or the http:// address of a Battlesnake
+        /// server, one per snake
+        #[arg(long = "bot", required = true)]
+        bots: Vec<String>,
+        /// Milliseconds a bot has to answer each tick
+        #[arg(long, default_value_t = bot::DEFAULT_BUDGET.as_millis() as u64)]
+        budget: u64,
+        /// What happens to a bot that is too slow or makes no sense
+        #[arg(long, default_value_t = bot::OnFailure::KeepStraight)]
+        on_failure: bot::OnFailure,
+        /// Give up on the match after this many ticks
+        #[arg(long, default_value_t = 10_000)]
+        max_ticks: u64,
+        /// Play as fast as possible without a window and print the result
+        #[arg(long, default_value_t = false)]
+        headless: bool,
+    },
+    /// Run a round robin between bots on every seed and board size, headless
+    Tournament {
+        /// Bot taking part: builtin:greedy, builtin:random, a command or a
+        /// Battlesnake http:// address
+        #[arg(long = "bot", required = true)]
+        bots: Vec<String>,
+        /// Seeds 0 to this one excluded are played
+        #[arg(long, default_value_t = 10)]
+        seeds: u64,
+        /// Board size like 40x30, can be repeated. Defaults to --width and --height
+        #[arg(long = "size", value_parser = tournament::parse_size)]
+        sizes: Vec<(usize, usize)>,
+        /// Matches played at the same time
+        #[arg(long, default_value_t = 4)]
+        threads: usize,
+        #[arg(long, default_value_t = bot::DEFAULT_BUDGET.as_millis() as u64)]
+        budget: u64,
+        #[arg(long, default_value_t = bot::OnFailure::KeepStraight)]
+        on_failure: bot::OnFailure,
+        #[arg(long, default_value_t = 2_000)]
+        max_ticks: u64,
+        /// Write every match to this CSV file
+        #[arg(long)]
+        csv: Option<String>,
+        /// Write the standings and every match to this JSON file
+        #[arg(long)]
+        json: Option<String>,
+    },
+    /// Play on a snake server, or list its rooms when no room is given
+    Connect {
+        addr: String,
+        #[arg(long)]
+        room: Option<String>,
+        #[arg(long, default_value = "player")]
+        name: String,
+    },
 }
 //CLI END
 
@@ -68,22 +596,24 @@ pub fn rgb(red: u8, green: u8, blue: u8) -> u32 {
     let new_red = a << 16;
     let new_green = b << 8;
 
-    let final_number = new_red | new_green | c;
-
-    return final_number;
+    new_red | new_green | c
 }
 //COLOURS MANAGEMENT END
 
-pub fn snake_generator(world: &mut World, buffer: &WindowBuffer, cli: &Cli) {
+/// Lays out the snakes in the middle of the board. Each player's handicap
+/// decides their starting length, speed and extra lives.
+pub fn snake_generator(world: &mut World, buffer: &impl Dimensions, cli: &Cli) {
     let x_middle_point = buffer.width() / 2;
     let y_middle_point = buffer.height() / 2;
+    let handicaps = [cli.handicap(0), cli.handicap(1)];
+    let length = |player: usize| handicaps[player].length.unwrap_or(cli.snake_size_start);
 
-    for i in 1..cli.snake_size_start + 1 {
+    for i in 1..length(0) + 1 {
         world.snake.push((x_middle_point - i, y_middle_point));
     }
 
-    if cli.two_players_mode == true {
-        for i in 1..cli.snake_size_start + 1 {
+    if cli.two_players_mode {
+        for i in 1..length(1) + 1 {
             world
                 .second_snake
                 .as_mut()
@@ -92,10 +622,26 @@ pub fn snake_generator(world: &mut World, buffer: &WindowBuffer, cli: &Cli) {
         }
     }
     world.snake.reverse();
+
+    if cli.tron && cli.snake_speed.is_none() {
+        world.snake_speed = TRON_SPEED;
+    }
+    world.second_snake_speed = handicaps[1].speed.unwrap_or(world.snake_speed);
+    if let Some(speed) = handicaps[0].speed {
+        world.snake_speed = speed;
+    }
+    world.lives = [
+        cli.lives + handicaps[0].extra_lives,
+        cli.lives + handicaps[1].extra_lives,
+    ];
+    world.spawns = [
+        world.snake.clone(),
+        world.second_snake.clone().unwrap_or_default(),
+    ];
 }
 
 pub fn snakes_collision_checker(world: &World, cli: &Cli) -> bool {
-    if cli.two_players_mode == true {
+    if cli.two_players_mode {
         let first_snake_head = world.snake[world.snake.len() - 1];
         let mut first_snake_body = world.snake.clone();
         first_snake_body.pop();
@@ -116,32 +662,60 @@ pub fn snakes_collision_checker(world: &World, cli: &Cli) -> bool {
             .iter()
             .any(|(a, b)| (a, b) == (&second_snake_head.0, &second_snake_head.1));
 
-        if checker_first_snake_into_second == true {
+        if checker_first_snake_into_second {
             return true;
         }
-        if checker_second_snake_into_first == true {
+        if checker_second_snake_into_first {
             return true;
         }
-        if (checker_first_snake_into_second == false) && (checker_second_snake_into_first == false)
-        {
+        if (!checker_first_snake_into_second) && (!checker_second_snake_into_first) {
             return false;
         }
 
         unreachable!("Problem with collision checker");
     } else {
-        return false;
+        false
     }
 }
 
+#[cfg(feature = "gui")]
 pub fn display(world: &World, buffer: &mut WindowBuffer, cli: &Cli) {
     buffer.reset();
-    world
-        .snake
-        .iter()
-        .for_each(|(x, y)| buffer[(x.clone(), y.clone())] = world.first_snake_colour);
-    buffer[world.snake[world.snake.len() - 1]] = world.first_snake_head_colour;
+    if cli.hud {
+        hud(world, buffer, cli);
+    }
+    let (width, height) = (buffer.width(), buffer.height());
+    for ring in 0..world.border {
+        for cell in border::ring(ring, width, height) {
+            buffer[cell] = border::WALL_COLOUR;
+        }
+    }
+    if let Some(every) = cli.shrink_interval() {
+        if border::can_shrink(world.border, width, height)
+            && border::flashing(world.shrink_elapsed, every)
+        {
+            for cell in border::ring(world.border, width, height) {
+                buffer[cell] = border::WARNING_COLOUR;
+            }
+        }
+    }
+    for (index, portal) in world.portals.iter().enumerate() {
+        portal
+            .ends
+            .iter()
+            .for_each(|end| buffer[*end] = Portal::colour(index));
+    }
+    // Snakes waiting to respawn blink.
+    let shown = |player: usize| world.respawn_wait[player] % 2 == 0;
+    if shown(0) {
+        world
+            .snake
+            .iter()
+            .for_each(|(x, y)| buffer[(*x, *y)] = world.first_snake_colour);
+        buffer[world.snake[world.snake.len() - 1]] = world.first_snake_head_colour;
+    }
 
-    if cli.two_players_mode == true && world.second_snake != None {
+    if cli.two_players_mode && world.second_snake.is_some() && shown(1) {
         if let Some(second_snake) = &world.second_snake {
             world
                 .second_snake
@@ -153,46 +727,142 @@ pub fn display(world: &World, buffer: &mut WindowBuffer, cli: &Cli) {
         }
     }
 
-    buffer[world.food] = world.food_colour;
+    let food_settings = FoodSettings::from_cli(cli);
+    if food_settings.visible(world.food_age) && !cli.tron {
+        buffer[world.food] = world.food_colour;
+    }
+    for item in &world.foods {
+        if food_settings.visible(item.age) {
+            buffer[item.position] = item.colour(world.food_colour);
+        }
+    }
+    if let Some(power_up) = world.power_up {
+        buffer[power_up.position] = power_up.kind.colour();
+    }
 
     if let Some(pos) = world.bad_berries_position {
-        buffer[pos] = world.bad_berries_colour;
+        buffer[pos] = world.berry_colour(cli);
+    }
+
+    pause_overlay(world, buffer);
+}
+
+/// Writes each player's score in a top corner, in the colour of their snake.
+/// Drawn first, so it never hides the snakes or the food.
+#[cfg(feature = "gui")]
+pub fn hud(world: &World, buffer: &mut WindowBuffer, cli: &Cli) {
+    text::draw_text(
+        buffer,
+        &world.score.to_string(),
+        1,
+        1,
+        world.first_snake_colour,
+    );
+
+    if cli.two_players_mode {
+        let score = world.second_score.to_string();
+        let x = buffer.width().saturating_sub(text::text_width(&score) + 1);
+        text::draw_text(buffer, &score, x, 1, world.second_snake_colour);
+    }
+
+    // Lives left show as dots under the scores.
+    let players = if cli.two_players_mode { 2 } else { 1 };
+    let colours = [world.first_snake_colour, world.second_snake_colour];
+    let y = text::GLYPH_HEIGHT + 2;
+    for (player, colour) in colours.into_iter().enumerate().take(players) {
+        for life in 0..world.lives[player] {
+            let x = match player {
+                0 => Some(1 + 2 * life),
+                _ => buffer.width().checked_sub(2 + 2 * life),
+            };
+            if let Some(x) = x.filter(|x| *x < buffer.width() && y < buffer.height()) {
+                buffer[(x, y)] = colour;
+            }
+        }
+    }
+
+    // Running effects count down from the bottom corners.
+    for (player, colour) in colours.into_iter().enumerate().take(players) {
+        let lines = world.effects[player].active.iter().rev().enumerate();
+        for (line, active) in lines {
+            let label = format!("{} {}", active.effect.label(), active.moves_left);
+            let y = (line + 1) * (text::GLYPH_HEIGHT + 1);
+            let Some(y) = buffer.height().checked_sub(y) else {
+                break;
+            };
+            let x = match player {
+                0 => 1,
+                _ => buffer.width().saturating_sub(text::text_width(&label) + 1),
+            };
+            text::draw_text(buffer, &label, x, y, colour);
+        }
     }
 }
 
+/// Dims the board and writes "PAUSED", or the seconds left before resuming.
+#[cfg(feature = "gui")]
+pub fn pause_overlay(world: &World, buffer: &mut WindowBuffer) {
+    let message = match world.pause {
+        PauseState::Running => return,
+        PauseState::Paused => "PAUSED".to_string(),
+        PauseState::Resuming(until) => {
+            let left = until.saturating_duration_since(world.clock.now());
+            (left.as_millis().div_ceil(1000).max(1)).to_string()
+        }
+    };
+
+    text::dim(buffer);
+    text::draw_centered_text(buffer, &message, rgb(u8::MAX, u8::MAX, u8::MAX));
+}
+
+#[cfg(feature = "gui")]
 pub fn go_display(world: &mut World, buffer: &mut WindowBuffer, cli: &Cli) {
     buffer.reset();
+    let winner = world.result.and_then(|result| result.winner);
+    let colour = |player: usize, own: u32| {
+        if winner == Some(player) {
+            own
+        } else {
+            rgb(u8::MAX, 0, 0)
+        }
+    };
+
+    let first_snake_colour = colour(0, world.first_snake_colour);
     world
         .snake
         .iter()
-        .for_each(|(x, y)| buffer[(x.clone(), y.clone())] = rgb(u8::MAX, 0, 0));
+        .for_each(|(x, y)| buffer[(*x, *y)] = first_snake_colour);
 
     if cli.two_players_mode == true && world.second_snake != None {
+        let second_snake_colour = colour(1, world.second_snake_colour);
         world
             .second_snake
             .clone()
             .unwrap()
             .iter()
-            .for_each(|(x, y)| buffer[(x.clone(), y.clone())] = rgb(u8::MAX, 0, 0));
+            .for_each(|(x, y)| buffer[(*x, *y)] = second_snake_colour);
     }
 
     buffer[world.food] = rgb(u8::MAX, 0, 0);
 }
 
 pub fn return_in_time(world: &mut World, cli: &Cli) {
-    if world.finished == true {
+    if world.finished {
         world.finished = false
     }
-    if world.reversed_snake.is_empty() == false {
-        let mut time_turning_snake: Vec<(usize, usize)> = Vec::new();
-
+    world.result = None;
+    if let Some((effects, power_up)) = world.rewind_history.pop() {
+        world.effects = effects;
+        world.power_up = power_up;
+    }
+    if !world.reversed_snake.is_empty() {
         let mut previous_position = world.reversed_snake.pop();
 
         if previous_position.unwrap() == world.snake[0] {
             previous_position = world.reversed_snake.pop();
         }
 
-        time_turning_snake = world
+        let mut time_turning_snake: Vec<(usize, usize)> = world
             .snake
             .windows(2)
             .rev()
@@ -207,16 +877,14 @@ pub fn return_in_time(world: &mut World, cli: &Cli) {
         world.snake = time_turning_snake;
     }
 
-    if cli.two_players_mode == true && world.reversed_second_snake != None {
-        let mut time_turning_second_snake: Vec<(usize, usize)> = Vec::new();
-
+    if cli.two_players_mode && world.reversed_second_snake.is_some() {
         let mut previous_position_second_snake = world.reversed_second_snake.clone().unwrap().pop();
 
         if previous_position_second_snake.unwrap() == world.second_snake.clone().unwrap()[0] {
             previous_position_second_snake = world.reversed_second_snake.clone().unwrap().pop();
         }
 
-        time_turning_second_snake = world
+        let mut time_turning_second_snake: Vec<(usize, usize)> = world
             .second_snake
             .clone()
             .unwrap()
@@ -234,7 +902,15 @@ pub fn return_in_time(world: &mut World, cli: &Cli) {
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+/// What `World::finish_move` needs from before a snake moved.
+struct MoveStart {
+    finished: bool,
+    tail: Option<(usize, usize)>,
+    head: Option<(usize, usize)>,
+    score: usize,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub enum Direction {
     Still,
     North,
@@ -243,15 +919,158 @@ pub enum Direction {
     South,
 }
 
+impl Direction {
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+            Direction::Still => Direction::Still,
+        }
+    }
+}
+
+//TURN QUEUE
+/// How many turns a player can buffer ahead of the snake.
+pub const TURN_QUEUE_CAPACITY: usize = 3;
+
+/// Direction the snake was actually moving in on the last tick it moved,
+/// or `spawn_heading` if it has not moved yet.
+pub fn heading(history: &[Direction], spawn_heading: Direction) -> Direction {
+    history
+        .iter()
+        .rev()
+        .find(|direction| **direction != Direction::Still)
+        .cloned()
+        .unwrap_or(spawn_heading)
+}
+
+pub(crate) fn queue_turn(
+    queue: &mut VecDeque<Direction>,
+    history: &[Direction],
+    spawn_heading: Direction,
+    turn: Direction,
+) {
+    let previous = queue
+        .back()
+        .cloned()
+        .unwrap_or_else(|| heading(history, spawn_heading));
+    // A snake standing still can set off in its heading, it isn't a repeat.
+    // Games that start still record a `Still` first, so an empty history
+    // means the snake was set off in its spawn heading before any move was
+    // recorded: it's already going that way.
+    let moving = queue.back().or(history.last()) != Some(&Direction::Still);
+
+    if queue.len() < TURN_QUEUE_CAPACITY
+        && !(moving && turn == previous)
+        && turn != previous.opposite()
+    {
+        queue.push_back(turn);
+    }
+}
+
+pub(crate) fn next_turn(
+    queue: &mut VecDeque<Direction>,
+    history: &[Direction],
+    spawn_heading: Direction,
+) -> Option<Direction> {
+    let current = heading(history, spawn_heading);
+
+    while let Some(turn) = queue.pop_front() {
+        if turn != current.opposite() {
+            return Some(turn);
+        }
+    }
+    None
+}
+//TURN QUEUE END
+
+//STATE HASH
+/// FNV-1a, with integers always fed in little endian so two machines
+/// running the same game agree on the hash.
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_isize(&mut self, value: isize) {
+        self.write_u64(value as u64);
+    }
+}
+//STATE HASH END
+
+//BOARD SIZE
+/// Width and height of the board the rules play on. The window's buffer has
+/// them, `BoardSize` stands in for it where there is no window, like on the
+/// server.
+pub trait Dimensions {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BoardSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Dimensions for BoardSize {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+}
+
+#[cfg(feature = "gui")]
+impl Dimensions for WindowBuffer {
+    fn width(&self) -> usize {
+        WindowBuffer::width(self)
+    }
+
+    fn height(&self) -> usize {
+        WindowBuffer::height(self)
+    }
+}
+//BOARD SIZE END
+
 //WORLD CREATION
+/// Pace of the steps taken back in time while rewinding.
+pub const REWIND_SPEED: Duration = Duration::from_millis(100);
+
 pub struct World {
     pub current_direction_first_snake: Direction,
     pub first_snake_directions: Vec<Direction>,
     pub snake: Vec<(usize, usize)>,
     pub food: (usize, usize),
     pub finished: bool,
-    pub small_break_timer: Instant,
-    pub space_count: usize,
+    pub pause: PauseState,
     pub snake_speed: usize,
     pub score: usize,
     pub bad_berries: usize,
@@ -269,17 +1088,59 @@ pub struct World {
     pub second_snake_head_colour: u32,
     pub food_colour: u32,
     pub bad_berries_colour: u32,
+    pub first_snake_turns: VecDeque<Direction>,
+    pub second_snake_turns: VecDeque<Direction>,
+    pub difficulty: DifficultyProfile,
+    pub foods_spawned: usize,
+    /// How a two players game ended, once it has.
+    pub result: Option<VersusResult>,
+    /// The second snake's tick interval, `snake_speed` being the first's.
+    pub second_snake_speed: usize,
+    /// Bad berries eaten by the second snake, `bad_berries` counting the first's.
+    pub second_bad_berries: usize,
+    /// Extra lives each player has left.
+    pub lives: [usize; 2],
+    /// Own moves each snake still waits after losing a life.
+    pub respawn_wait: [u64; 2],
+    /// Where each snake started, to come back there after losing a life.
+    pub spawns: [Vec<(usize, usize)>; 2],
+    pub berries: BerryConfig,
+    /// Index in the mode's berry kinds of the bad berry on the board.
+    pub berry_kind: usize,
+    /// Timed effects running on each snake.
+    pub effects: [Effects; 2],
+    pub berry_bites: [Option<BerryBite>; 2],
+    /// Food items besides `food`.
+    pub foods: Vec<FoodItem>,
+    /// Moves of the first snake since the last food item appeared.
+    pub food_ticks: u64,
+    /// Moves of the first snake since `food` showed up where it is.
+    pub food_age: u64,
+    pub power_up: Option<PowerUp>,
+    pub portals: Vec<Portal>,
+    /// Rings of wall closed in around the board by `--shrink-every`.
+    pub border: usize,
+    /// Game time since the last ring closed in, counted in the first snake's
+    /// tick intervals so it stops along with the game.
+    pub shrink_elapsed: Duration,
+    /// Effects and power-up from before each move of the first snake, for
+    /// rewinding.
+    pub rewind_history: Vec<([Effects; 2], Option<PowerUp>)>,
+    pub clock: Arc<dyn Clock>,
+    /// Every random choice the game makes comes from here, so seeding it
+    /// makes a game reproducible.
+    pub rng: StdRng,
 }
 
 impl World {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         current_direction_first_snake: Direction,
         first_snake_directions: Vec<Direction>,
         snake: Vec<(usize, usize)>,
         food: (usize, usize),
         finished: bool,
-        small_break_timer: Instant,
-        space_count: usize,
+        pause: PauseState,
         snake_speed: usize,
         score: usize,
         bad_berries: usize,
@@ -304,8 +1165,7 @@ impl World {
             snake,
             food,
             finished,
-            small_break_timer,
-            space_count,
+            pause,
             snake_speed,
             score,
             bad_berries,
@@ -323,1865 +1183,1403 @@ impl World {
             second_snake_head_colour,
             food_colour,
             bad_berries_colour,
+            first_snake_turns: VecDeque::new(),
+            second_snake_turns: VecDeque::new(),
+            difficulty: DifficultyProfile::default(),
+            foods_spawned: 0,
+            result: None,
+            second_snake_speed: snake_speed,
+            second_bad_berries: 0,
+            lives: [0, 0],
+            respawn_wait: [0, 0],
+            spawns: [Vec::new(), Vec::new()],
+            berries: BerryConfig::default(),
+            berry_kind: 0,
+            effects: [Effects::default(), Effects::default()],
+            berry_bites: [None, None],
+            foods: Vec::new(),
+            food_ticks: 0,
+            food_age: 0,
+            portals: Vec::new(),
+            border: 0,
+            shrink_elapsed: Duration::ZERO,
+            power_up: None,
+            rewind_history: Vec::new(),
+            clock: Arc::new(SystemClock),
+            rng: StdRng::from_entropy(),
         }
     }
 
-    pub fn update(&mut self, buffer: &mut WindowBuffer, cli: &Cli) {
-        if self.space_count % 2 == 0 {
-            self.direction(buffer, cli);
-            self.snake_update(buffer, cli);
+    /// An empty world for a new game at `difficulty`, in the usual colours,
+    /// waiting for `snake_generator` and `food_generator`.
+    pub fn starting(difficulty: DifficultyProfile, clock: Arc<dyn Clock>) -> Self {
+        let mut world = World::new(
+            Direction::Still,
+            vec![Direction::Still],
+            Vec::new(),
+            (0, 0),
+            false,
+            PauseState::Running,
+            difficulty.starting_speed,
+            0,
+            0,
+            None,
+            Vec::new(),
+            TimeCycle::Forward,
+            Some(Vec::new()),
+            vec![Direction::Still],
+            Some(Vec::new()),
+            Direction::Still,
+            0,
+            0xFF33CCFF,
+            0xFFCC66FF,
+            0xFFCC33FF,
+            0xFFFFCC00,
+            0xFF00FF00,
+            0xFFFF0000,
+        );
+        world.difficulty = difficulty;
+        world.clock = clock;
+        world
+    }
+
+    pub fn update(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        self.update_first_snake(buffer, cli);
 
-            if cli.two_players_mode == true {
-                self.direction_second_snake(buffer, cli);
-                self.second_snake_update(buffer, cli);
-            }
+        if cli.two_players_mode {
+            self.update_second_snake(buffer, cli);
         }
     }
 
-    pub fn reset(&mut self) {
-        self.snake.clear();
-        if let Some(mut pos) = self.second_snake.clone() {
-            pos.clear();
+    pub fn update_first_snake(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        if self.pause == PauseState::Running && self.result.is_none() && !self.wait_to_respawn(0) {
+            if let Some(turn) = next_turn(
+                &mut self.first_snake_turns,
+                &self.first_snake_directions,
+                Direction::East,
+            ) {
+                self.current_direction_first_snake = self.steer(0, turn);
+            }
+            let start = self.start_move(0);
+            let cli = self.cli_for(0, cli);
+            self.direction(buffer, &cli);
+            self.snake_update(buffer, &cli);
+            self.finish_move(0, start, buffer, &cli);
         }
     }
 
-    pub fn food_generator(&mut self, buffer: &WindowBuffer, cli: &Cli) {
-        loop {
-            let x = rand::thread_rng().gen_range(0..buffer.width());
-            let y = rand::thread_rng().gen_range(0..buffer.height());
-            let v: usize = rand::thread_rng().gen_range(0..buffer.width());
-            let w: usize = rand::thread_rng().gen_range(0..buffer.height());
-
-            let checker_1 = self.snake.iter().any(|(a, b)| (a, b) == (&x, &y));
-            let checker_2 = self.snake.iter().any(|(a, b)| (a, b) == (&v, &w));
-
-            if checker_1 == true || (x == v && y == w) || checker_2 == true {
-                continue;
-            } else {
-                self.food = (x, y);
-                if cli.bad_berries == true {
-                    self.bad_berries_position = Some((v, w));
-                }
-                return;
+    pub fn update_second_snake(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        if self.pause == PauseState::Running && self.result.is_none() && !self.wait_to_respawn(1) {
+            if let Some(turn) = next_turn(
+                &mut self.second_snake_turns,
+                &self.second_snake_directions,
+                Direction::West,
+            ) {
+                self.current_direction_second_snake = self.steer(1, turn);
             }
+            let start = self.start_move(1);
+            let cli = self.cli_for(1, cli);
+            self.direction_second_snake(buffer, &cli);
+            self.second_snake_update(buffer, &cli);
+            self.finish_move(1, start, buffer, &cli);
         }
     }
 
-    pub fn handle_user_input<W: Graphic>(
+    /// Notes what `finish_move` needs from before `player`'s snake moves,
+    /// and keeps what a rewind has to give back.
+    fn start_move(&mut self, player: usize) -> MoveStart {
+        if player == 0 {
+            self.rewind_history
+                .push((self.effects.clone(), self.power_up));
+        }
+        let snake = match player {
+            0 => Some(&self.snake),
+            _ => self.second_snake.as_ref(),
+        };
+
+        MoveStart {
+            finished: self.finished,
+            tail: snake.and_then(|snake| snake.first().copied()),
+            head: snake.and_then(|snake| snake.last().copied()),
+            score: [self.score, self.second_score][player],
+        }
+    }
+
+    /// Everything that happens once `player`'s snake has moved, on top of
+    /// what `snake_update` does.
+    fn finish_move(
         &mut self,
-        window: &W,
+        player: usize,
+        start: MoveStart,
+        buffer: &impl Dimensions,
         cli: &Cli,
-        buffer: &WindowBuffer,
-    ) -> std::io::Result<()> {
-        if window.is_key_pressed(graphic::Key::Quit) {
-            self.reset();
+    ) {
+        if let (true, Some(tail)) = (cli.tron, start.tail) {
+            self.extend_trail(player, tail);
+        }
+        if self.head(player) != start.head {
+            self.teleport(player);
         }
+        self.settle_berry(player, cli);
+        if let Some(tail) = start.tail {
+            self.eat_food_items(player, tail, buffer, cli);
+        }
+        self.collect_power_up(player);
+        self.multiply_score(player, start.score);
+        self.pull_food(player);
+        if player == 0 && !cli.tron {
+            self.food_timer(buffer, cli);
+            self.age_food(buffer, cli);
+        }
+        self.effects[player].tick();
+        let ploughed = self.plough(player, cli);
+        self.bite(player, cli);
+        let squeezed = match player {
+            0 if self.head(0) != start.head => self.close_in(buffer, cli),
+            _ => None,
+        };
+        self.settle_versus(player, start.finished, ploughed.or(squeezed), buffer, cli);
+        if !cli.two_players_mode && self.finished && !start.finished && self.lives[player] > 0 {
+            self.finished = false;
+            self.lose_life(player, buffer);
+        }
+    }
 
-        if window.is_key_pressed(graphic::Key::Save) {
-            let mut save_file = File::create("save_file")?;
+    /// Light cycles keep the cell their tail left, scoring a point for it.
+    fn extend_trail(&mut self, player: usize, tail: (usize, usize)) {
+        let Some(snake) = self.snake_mut(player) else {
+            return;
+        };
+        if snake[0] != tail {
+            snake.insert(0, tail);
+            *self.score_mut(player) += 1;
+        }
+    }
 
-            if cli.file_path != None {
-                save_file = File::create(cli.file_path.clone().unwrap())?;
-            }
-            save_file.write_all(&buffer.width().to_be_bytes())?;
-            save_file.write_all(&buffer.height().to_be_bytes())?;
-            save_file.write_all(&self.food.0.to_be_bytes())?;
-            save_file.write_all(&self.food.1.to_be_bytes())?;
+    fn snake_mut(&mut self, player: usize) -> Option<&mut Vec<(usize, usize)>> {
+        let snake = match player {
+            0 => Some(&mut self.snake),
+            _ => self.second_snake.as_mut(),
+        };
+        snake.filter(|snake| !snake.is_empty())
+    }
 
-            /*save_file.write_all(&self.speed().to_be_bytes())?;
-            save_file.write_all(&self.snake.to_be_bytes())?;
+    fn head(&self, player: usize) -> Option<(usize, usize)> {
+        match player {
+            0 => self.snake.last().copied(),
+            _ => self.second_snake.as_ref()?.last().copied(),
+        }
+    }
 
-            for number in &self.snake {
-                save_file.write_all(&number.to_be_bytes())?;
-            } */
+    /// Starts the effect of the power-up `player`'s head is on, if any. The
+    /// HUD counts it down in the player's corner.
+    fn collect_power_up(&mut self, player: usize) {
+        let Some(power_up) = self.power_up else {
+            return;
+        };
+        if self.head(player) != Some(power_up.position) {
+            return;
+        }
+        self.power_up = None;
 
-            save_file.flush()?;
+        let spec = power_up.kind.effect();
+        self.effects[player].start(&spec);
+        if let (Effect::Shrink(segments), Some(snake)) = (spec.effect, self.snake_mut(player)) {
+            let cut = segments.min(snake.len().saturating_sub(2));
+            snake.drain(..cut);
         }
+    }
 
-        if window.is_key_pressed(graphic::Key::Up) {
-            self.time_cycle = TimeCycle::Forward;
-            if self.first_snake_directions[self.first_snake_directions.len() - 1]
-                != Direction::South
-            {
-                self.current_direction_first_snake = Direction::North;
-            }
+    fn score_mut(&mut self, player: usize) -> &mut usize {
+        match player {
+            0 => &mut self.score,
+            _ => &mut self.second_score,
         }
+    }
 
-        if window.is_key_pressed(graphic::Key::Down) {
-            self.time_cycle = TimeCycle::Forward;
-            if self.first_snake_directions[self.first_snake_directions.len() - 1]
-                != Direction::North
-            {
-                self.current_direction_first_snake = Direction::South;
+    /// Multiplies the points `player` scored during the move while a score
+    /// multiplier runs.
+    fn multiply_score(&mut self, player: usize, score_before: usize) {
+        let times = self.effects[player].score_multiplier();
+        let score = self.score_mut(player);
+        if *score > score_before {
+            *score += (*score - score_before) * (times - 1);
+        }
+    }
+
+    /// Pulls the food in range one cell closer while a magnet runs.
+    fn pull_food(&mut self, player: usize) {
+        if !self.effects[player].has(Effect::Magnet) {
+            return;
+        }
+        let Some(head) = self.head(player) else {
+            return;
+        };
+
+        if let Some(cell) = power_ups::pull(self.food, head) {
+            if !self.is_taken(cell) {
+                self.food = cell;
             }
         }
+        for index in 0..self.foods.len() {
+            if let Some(cell) = power_ups::pull(self.foods[index].position, head) {
+                if !self.is_taken(cell) {
+                    self.foods[index].position = cell;
+                }
+            }
+        }
+    }
 
-        if window.is_key_pressed(graphic::Key::Left) {
-            self.time_cycle = TimeCycle::Forward;
-            if self.first_snake_directions[self.first_snake_directions.len() - 1] != Direction::East
-            {
-                let mut vec_to_check: Vec<Direction> = self.first_snake_directions.clone();
-                vec_to_check.dedup();
-                if vec_to_check.len() != 1 {
-                    self.current_direction_first_snake = Direction::West;
+    /// Whether anything is on `cell`.
+    fn is_taken(&self, cell: (usize, usize)) -> bool {
+        self.snake.contains(&cell)
+            || self
+                .second_snake
+                .as_ref()
+                .is_some_and(|snake| snake.contains(&cell))
+            || self.food == cell
+            || self.bad_berries_position == Some(cell)
+            || self.foods.iter().any(|item| item.position == cell)
+            || self
+                .power_up
+                .is_some_and(|power_up| power_up.position == cell)
+            || self.is_portal(cell)
+    }
+
+    fn is_portal(&self, cell: (usize, usize)) -> bool {
+        portals::exit(&self.portals, cell).is_some()
+    }
+
+    /// Whether `cell` is still in play, not in a ring of wall.
+    fn in_arena(&self, cell: (usize, usize), buffer: &impl Dimensions) -> bool {
+        border::inside(cell, self.border, buffer.width(), buffer.height())
+    }
+
+    /// Where something at `position` moving towards `direction` ends up,
+    /// `None` if that is into a wall, rings of wall included.
+    fn step(
+        &self,
+        position: (usize, usize),
+        direction: &Direction,
+        buffer: &impl Dimensions,
+        cli: &Cli,
+    ) -> Option<(usize, usize)> {
+        border::step(
+            cli.board_topology(),
+            position,
+            direction,
+            self.border,
+            buffer.width(),
+            buffer.height(),
+        )
+    }
+
+    /// Counts the game time the first snake's move took and closes the next
+    /// ring of wall in once it's due. Whatever is caught in it goes: food
+    /// moves elsewhere, and snakes die, which in a two players game is
+    /// returned for `settle_versus` to judge.
+    fn close_in(&mut self, buffer: &impl Dimensions, cli: &Cli) -> Option<VersusResult> {
+        let every = cli.shrink_interval()?;
+        let (width, height) = (buffer.width(), buffer.height());
+        if !border::can_shrink(self.border, width, height) {
+            return None;
+        }
+        let speed = self.effects[0].speed(self.snake_speed);
+        self.shrink_elapsed += Duration::from_millis(speed as u64);
+        if self.shrink_elapsed < every {
+            return None;
+        }
+        self.shrink_elapsed = Duration::ZERO;
+        self.border += 1;
+
+        self.portals.retain(|portal| {
+            portal
+                .ends
+                .iter()
+                .all(|end| border::inside(*end, self.border, width, height))
+        });
+        if !self.in_arena(self.food, buffer) {
+            if let Some(cell) = self.free_cell(buffer) {
+                self.food = cell;
+            }
+        }
+        for index in 0..self.foods.len() {
+            if !self.in_arena(self.foods[index].position, buffer) {
+                if let Some(cell) = self.free_cell(buffer) {
+                    self.foods[index].position = cell;
                 }
             }
         }
+        if self
+            .bad_berries_position
+            .is_some_and(|berry| !self.in_arena(berry, buffer))
+        {
+            self.bad_berries_position = None;
+        }
+        if self
+            .power_up
+            .is_some_and(|power_up| !self.in_arena(power_up.position, buffer))
+        {
+            self.power_up = None;
+        }
 
-        if window.is_key_pressed(graphic::Key::Right) {
-            self.time_cycle = TimeCycle::Forward;
-            if self.first_snake_directions[self.first_snake_directions.len() - 1] != Direction::West
-            {
-                self.current_direction_first_snake = Direction::East;
+        let caught = |snake: &[(usize, usize)]| {
+            snake
+                .iter()
+                .any(|cell| !border::inside(*cell, self.border, width, height))
+        };
+        let second_snake = self.second_snake.as_deref().unwrap_or_default();
+        let caught = [
+            caught(&self.snake),
+            cli.two_players_mode && caught(second_snake),
+        ];
+        let lengths = [self.snake.len(), second_snake.len()];
+        // Nothing shields from the wall.
+        for (effects, caught) in self.effects.iter_mut().zip(caught) {
+            if caught {
+                *effects = Effects::default();
             }
         }
 
-        if window.is_key_pressed(graphic::Key::UpPlayer2) {
-            if cli.two_players_mode == true {
-                if self.second_snake_directions[self.second_snake_directions.len() - 1]
-                    != Direction::South
-                {
-                    self.current_direction_second_snake = Direction::North;
-                }
+        if !cli.two_players_mode {
+            if caught[0] && !self.finished {
+                self.first_snake_crashed(cli);
             }
+            return None;
         }
+        let winner = match caught {
+            [false, false] => return None,
+            [true, false] => Some(1),
+            [false, true] => Some(0),
+            [true, true] => None,
+        };
+        Some(VersusResult {
+            winner,
+            reason: EndReason::HitWall,
+            lengths,
+        })
+    }
 
-        if window.is_key_pressed(graphic::Key::DownPlayer2) {
-            if cli.two_players_mode == true {
-                if self.second_snake_directions[self.second_snake_directions.len() - 1]
-                    != Direction::North
-                {
-                    self.current_direction_second_snake = Direction::South;
-                }
+    /// A random free cell, if one turns up soon enough.
+    fn free_cell(&mut self, buffer: &impl Dimensions) -> Option<(usize, usize)> {
+        for _ in 0..buffer.width() * buffer.height() {
+            let cell = (
+                self.rng.gen_range(0..buffer.width()),
+                self.rng.gen_range(0..buffer.height()),
+            );
+            if !self.is_taken(cell) && self.in_arena(cell, buffer) {
+                return Some(cell);
             }
         }
+        None
+    }
 
-        if window.is_key_pressed(graphic::Key::LeftPlayer2) {
-            if cli.two_players_mode == true {
-                if self.second_snake_directions[self.second_snake_directions.len() - 1]
-                    != Direction::East
-                {
-                    self.current_direction_second_snake = Direction::West;
-                }
+    /// Sends `player`'s head, which just moved into a portal, out of the other
+    /// end of the pair. A head moving onto a body stays there, so the crash
+    /// is noticed like anywhere else.
+    fn teleport(&mut self, player: usize) {
+        let Some(head) = self.head(player) else {
+            return;
+        };
+        let Some(exit) = portals::exit(&self.portals, head) else {
+            return;
+        };
+        let bodies = [Some(&self.snake), self.second_snake.as_ref()];
+        let on_body = bodies.into_iter().flatten().any(|snake| {
+            let body = &snake[..snake.len().saturating_sub(1)];
+            body.contains(&head)
+        });
+        if on_body {
+            return;
+        }
+
+        if let Some(snake) = self.snake_mut(player) {
+            let last = snake.len() - 1;
+            snake[last] = exit;
+        }
+    }
+
+    /// Puts the portals given on the command line on the board, leaving out
+    /// those that don't fit, then the random ones on free cells.
+    pub fn portal_generator(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        self.portals = cli
+            .portals
+            .iter()
+            .filter(|portal| portal.fits(buffer.width(), buffer.height()))
+            .copied()
+            .collect();
+
+        for _ in 0..cli.random_portals {
+            let (Some(from), Some(to)) = (self.free_cell(buffer), self.free_cell(buffer)) else {
+                break;
+            };
+            if from != to {
+                self.portals.push(Portal { ends: [from, to] });
             }
         }
 
-        if window.is_key_pressed(graphic::Key::RightPlayer2) {
-            if cli.two_players_mode == true {
-                if self.second_snake_directions[self.second_snake_directions.len() - 1]
-                    != Direction::West
-                {
-                    let mut vec_to_check_second: Vec<Direction> =
-                        self.second_snake_directions.clone();
-                    vec_to_check_second.dedup();
-                    if vec_to_check_second.len() != 1 {
-                        self.current_direction_second_snake = Direction::East;
-                    }
-                }
+        if self.is_portal(self.food) {
+            if let Some(cell) = self.free_cell(buffer) {
+                self.food = cell;
             }
         }
+    }
 
-        let small_break = Duration::from_millis(0);
-        if self.small_break_timer.elapsed() >= small_break {
-            window.get_keys_released().iter().for_each(|key| match key {
-                graphic::Key::Space => self.space_count += 1,
-                _ => (),
+    /// While invincible, a snake cuts off whatever body it runs into, its own
+    /// or the other snake's, and wins a head-on collision.
+    fn plough(&mut self, player: usize, cli: &Cli) -> Option<VersusResult> {
+        if !self.effects[player].has(Effect::Invincible) {
+            return None;
+        }
+        let head = self.head(player)?;
+        if let Some(snake) = self.snake_mut(player) {
+            cut_tail(snake, head);
+        }
+        if !cli.two_players_mode {
+            return None;
+        }
+
+        let other = 1 - player;
+        if self.head(other) == Some(head) {
+            let lengths = [
+                self.snake.len(),
+                self.second_snake.as_ref().map_or(0, Vec::len),
+            ];
+            return Some(VersusResult {
+                winner: Some(player),
+                reason: EndReason::HeadOn,
+                lengths,
             });
-            self.small_break_timer = Instant::now();
         }
+        if let Some(snake) = self.snake_mut(other) {
+            cut_tail(snake, head);
+        }
+        None
+    }
 
-        if window.is_key_pressed(graphic::Key::Backward) {
-            self.time_cycle = TimeCycle::Backward;
+    /// With tail-biting, `player`'s head on a body cuts that body instead of
+    /// crashing into it. Segments bitten off their own snake cost them a food
+    /// each; those bitten off the other snake are theirs, points included.
+    fn bite(&mut self, player: usize, cli: &Cli) {
+        if !cli.tail_biting {
+            return;
+        }
+        let Some(head) = self.head(player) else {
+            return;
+        };
+        let worth = self.difficulty.food_score();
+
+        let lost = self
+            .snake_mut(player)
+            .map_or(0, |snake| cut_tail(snake, head));
+        if lost > 0 {
+            let score = self.score_mut(player);
+            *score = score.saturating_sub(lost * worth);
+            return;
+        }
+        if !cli.two_players_mode {
+            return;
         }
 
-        if window.is_key_pressed(graphic::Key::Forward) {
-            self.time_cycle = TimeCycle::Forward;
+        let other = 1 - player;
+        let stolen = self
+            .snake_mut(other)
+            .map_or(0, |snake| cut_tail(snake, head));
+        if stolen == 0 {
+            return;
+        }
+        let score = self.score_mut(other);
+        *score = score.saturating_sub(stolen * worth);
+        *self.score_mut(player) += stolen * worth;
+        // Like any growth, the new segments unfold from the tail.
+        if let Some(snake) = self.snake_mut(player) {
+            let tail = snake[0];
+            snake.splice(..0, std::iter::repeat_n(tail, stolen));
         }
+    }
 
-        Ok(())
+    /// Adds food items on free cells until there are as many as wanted, or
+    /// no free cell turns up.
+    pub fn top_up_foods(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        let wanted = FoodSettings::from_cli(cli).extra();
+        while self.foods.len() < wanted && self.spawn_food_item(buffer, cli) {}
     }
 
-    pub fn snake_update(&mut self, buffer: &WindowBuffer, cli: &Cli) {
-        let snake_collision_check = snakes_collision_checker(&self, cli);
-        let mut reversed_vector: Vec<(usize, usize)> = Vec::new();
+    fn spawn_food_item(&mut self, buffer: &impl Dimensions, cli: &Cli) -> bool {
+        let Some(position) = self.free_cell(buffer) else {
+            return false;
+        };
+        let mut item = FoodItem::new(position, &mut self.rng);
+        item.runaway = cli.runaway_food && self.rng.gen_ratio(1, food::RUNAWAY_CHANCE);
+        self.foods.push(item);
+        true
+    }
 
-        let head = self.snake[self.snake.len() - 1];
-        let mut snake_body = self.snake.clone();
-        snake_body.pop();
-        let checker = snake_body.iter().any(|(a, b)| (a, b) == (&head.0, &head.1));
-
-        if self.snake[self.snake.len() - 1] == self.food {
-            match self.current_direction_first_snake {
-                Direction::North => {
-                    if buffer.get(head.0 as isize, head.1 as isize - 1) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        self.snake.push((head.0, head.1));
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0, head.1 - 1));
-
-                        if cli.speed_increase == Difficulty::Hard {
-                            self.snake_speed = 120;
-                        }
-
-                        self.food_generator(&buffer, cli);
-
-                        self.score += 10;
-
-                        self.first_snake_directions.push(Direction::North);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::North);
-                        } */
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            self.snake.push((head.0, head.1));
-                            reversed_vector = self
-                                .snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, buffer.height() - 1));
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.score += 10;
-
-                            self.first_snake_directions.push(Direction::North);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::North);
-                            } */
-                        } else {
-                            self.current_direction_first_snake = Still;
-                            reversed_vector = self.snake.clone();
-                            self.finished = true;
-                            if cli.two_players_mode == true {
-                                println!(
-                                    "Player 1 score is {}, Player 2 score is {}",
-                                    self.score, self.second_score
-                                );
-                            } else {
-                                println!("Your score is {}", self.score);
-                            }
-
-                            self.first_snake_directions.push(Direction::Still);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::Still);
-                            } */
-                        }
-                    }
-                }
-                Direction::South => {
-                    if buffer.get(head.0 as isize, head.1 as isize + 1) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        self.snake.push((head.0, head.1));
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0, head.1 + 1));
-
-                        if cli.speed_increase == Difficulty::Hard {
-                            self.snake_speed = 120;
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.score += 10;
-
-                        self.first_snake_directions.push(Direction::South);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::South);
-                        }  */
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            self.snake.push((head.0, head.1));
-                            reversed_vector = self
-                                .snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, 0));
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.score += 10;
-
-                            self.first_snake_directions.push(Direction::South);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::South);
-                            } */
-                        } else {
-                            self.current_direction_first_snake = Still;
-                            reversed_vector = self.snake.clone();
-                            self.finished = true;
-                            if cli.two_players_mode == true {
-                                println!(
-                                    "Player 1 score is {}, Player 2 score is {}",
-                                    self.score, self.second_score
-                                );
-                            } else {
-                                println!("Your score is {}", self.score);
-                            }
-
-                            self.first_snake_directions.push(Direction::Still);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::Still);
-                            }  */
-                        }
-                    }
-                }
-                Direction::East => {
-                    if buffer.get(head.0 as isize + 1, head.1 as isize) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        self.snake.push((head.0, head.1));
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0 + 1, head.1));
-
-                        if cli.speed_increase == Difficulty::Hard {
-                            self.snake_speed = 120;
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.score += 10;
-
-                        self.first_snake_directions.push(Direction::East);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::East);
-                        } */
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            self.snake.push((head.0, head.1));
-                            reversed_vector = self
-                                .snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((0, head.1));
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.score += 10;
-                            self.first_snake_directions.push(Direction::East);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::East);
-                            } */
-                        } else {
-                            self.current_direction_first_snake = Still;
-                            reversed_vector = self.snake.clone();
-                            self.finished = true;
-                            if cli.two_players_mode == true {
-                                println!(
-                                    "Player 1 score is {}, Player 2 score is {}",
-                                    self.score, self.second_score
-                                );
-                            } else {
-                                println!("Your score is {}", self.score);
-                            }
-
-                            self.first_snake_directions.push(Direction::Still);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::Still);
-                            }  */
-                        }
-                    }
-                }
-                Direction::West => {
-                    if buffer.get(head.0 as isize - 1, head.1 as isize) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        self.snake.push((head.0, head.1));
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0 - 1, head.1));
-
-                        if cli.speed_increase == Difficulty::Hard {
-                            self.snake_speed = 120;
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.score += 10;
-
-                        self.first_snake_directions.push(Direction::West);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::West);
-                        }  */
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            self.snake.push((head.0, head.1));
-                            reversed_vector = self
-                                .snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((buffer.width() - 1, head.1));
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.score += 10;
-
-                            self.first_snake_directions.push(Direction::West);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::West);
-                            } */
-                        } else {
-                            self.current_direction_first_snake = Still;
-                            reversed_vector = self.snake.clone();
-                            self.finished = true;
-                            if cli.two_players_mode == true {
-                                println!(
-                                    "Player 1 score is {}, Player 2 score is {}",
-                                    self.score, self.second_score
-                                );
-                            } else {
-                                println!("Your score is {}", self.score);
-                            }
-
-                            self.first_snake_directions.push(Direction::Still);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::Still);
-                            }*/
-                        }
-                    }
-                }
-                Direction::Still => {
-                    reversed_vector = self.snake.clone();
-                    self.first_snake_directions.push(Direction::Still);
-                    /*
-                    if cli.two_players_mode == true {
-                        self.second_snake_directions.push(Direction::Still);
-                    } */
-                }
+    /// Eats the food item `player`'s head moved onto, if any: the snake
+    /// grows back its old `tail` and scores the item's worth.
+    fn eat_food_items(
+        &mut self,
+        player: usize,
+        tail: (usize, usize),
+        buffer: &impl Dimensions,
+        cli: &Cli,
+    ) {
+        let (snake, score, speed) = match player {
+            0 => (&mut self.snake, &mut self.score, &mut self.snake_speed),
+            _ => match self.second_snake.as_mut() {
+                Some(snake) => (snake, &mut self.second_score, &mut self.second_snake_speed),
+                None => return,
+            },
+        };
+        let head = snake[snake.len() - 1];
+        let Some(index) = self.foods.iter().position(|item| item.position == head) else {
+            return;
+        };
+
+        let item = self.foods.remove(index);
+        if snake[0] != tail {
+            snake.insert(0, tail);
+        }
+        *score += item.multiplier * self.difficulty.food_score();
+        *speed = cli
+            .handicap(player)
+            .speed_after_food(&self.difficulty, *speed);
+
+        if FoodSettings::from_cli(cli).spawn_ticks.is_none() {
+            self.top_up_foods(buffer, cli);
+        }
+    }
+
+    /// Counts the first snake's moves to add food items over time.
+    fn food_timer(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        let settings = FoodSettings::from_cli(cli);
+        if settings.spawn_ticks.is_none() {
+            return;
+        }
+        self.food_ticks += 1;
+        if settings.spawn_due(self.food_ticks) {
+            self.food_ticks = 0;
+            if self.foods.len() < settings.extra() {
+                self.spawn_food_item(buffer, cli);
             }
-            self.snake = reversed_vector;
-        } else if (self.bad_berries_position != None)
-            && (self.snake[self.snake.len() - 1] == self.bad_berries_position.unwrap())
-        {
-            self.bad_berries += 1;
+        }
+    }
 
-            if self.bad_berries % 2 != 0 {
-                self.snake_speed = self.snake_speed / 3;
-            } else if (self.bad_berries > 1) && (self.bad_berries % 2 == 0) {
-                self.snake_speed = self.snake_speed * 3;
+    /// Counts how long the food has been where it is, moves runaways away
+    /// from the closest head and makes expired food move elsewhere or go.
+    fn age_food(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        let settings = FoodSettings::from_cli(cli);
+
+        self.food_age += 1;
+        if settings.expired(self.food_age) {
+            if let Some(cell) = self.free_cell(buffer) {
+                self.food = cell;
             }
+            self.food_age = 0;
+        }
 
-            match self.current_direction_first_snake {
-                Direction::North => {
-                    if buffer.get(head.0 as isize, head.1 as isize - 1) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        self.snake.push((head.0, head.1));
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0, head.1 - 1));
-
-                        self.food_generator(&buffer, cli);
-                        self.score += 10;
-
-                        self.first_snake_directions.push(Direction::North);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::North);
-                        } */
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            self.snake.push((head.0, head.1));
-                            reversed_vector = self
-                                .snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, buffer.height() - 1));
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.score += 10;
-
-                            self.first_snake_directions.push(Direction::North);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::North);
-                            } */
-                        } else {
-                            self.current_direction_first_snake = Still;
-                            reversed_vector = self.snake.clone();
-                            self.finished = true;
-                            if cli.two_players_mode == true {
-                                println!(
-                                    "Player 1 score is {}, Player 2 score is {}",
-                                    self.score, self.second_score
-                                );
-                            } else {
-                                println!("Your score is {}", self.score);
-                            }
-
-                            self.first_snake_directions.push(Direction::Still);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::Still);
-                            } */
-                        }
-                    }
-                }
-                Direction::South => {
-                    if buffer.get(head.0 as isize, head.1 as isize + 1) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        self.snake.push((head.0, head.1));
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0, head.1 + 1));
-
-                        self.food_generator(&buffer, cli);
-                        self.score += 10;
-
-                        self.first_snake_directions.push(Direction::South);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::South);
-                        } */
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            self.snake.push((head.0, head.1));
-                            reversed_vector = self
-                                .snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, 0));
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.score += 10;
-
-                            self.first_snake_directions.push(Direction::South);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::South);
-                            } */
-                        } else {
-                            self.current_direction_first_snake = Still;
-                            reversed_vector = self.snake.clone();
-                            self.finished = true;
-                            if cli.two_players_mode == true {
-                                println!(
-                                    "Player 1 score is {}, Player 2 score is {}",
-                                    self.score, self.second_score
-                                );
-                            } else {
-                                println!("Your score is {}", self.score);
-                            }
-
-                            self.first_snake_directions.push(Direction::Still);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::Still);
-                            } */
-                        }
-                    }
-                }
-                Direction::East => {
-                    if buffer.get(head.0 as isize + 1, head.1 as isize) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        self.snake.push((head.0, head.1));
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0 + 1, head.1));
-
-                        self.food_generator(&buffer, cli);
-                        self.score += 10;
-
-                        self.first_snake_directions.push(Direction::East);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::East);
-                        } */
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            self.snake.push((head.0, head.1));
-                            reversed_vector = self
-                                .snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((0, head.1));
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.score += 10;
-
-                            self.first_snake_directions.push(Direction::East);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::East);
-                            } */
-                        } else {
-                            self.current_direction_first_snake = Still;
-                            reversed_vector = self.snake.clone();
-                            self.finished = true;
-                            if cli.two_players_mode == true {
-                                println!(
-                                    "Player 1 score is {}, Player 2 score is {}",
-                                    self.score, self.second_score
-                                );
-                            } else {
-                                println!("Your score is {}", self.score);
-                            }
-
-                            self.first_snake_directions.push(Direction::Still);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::Still);
-                            } */
-                        }
-                    }
-                }
-                Direction::West => {
-                    if buffer.get(head.0 as isize - 1, head.1 as isize) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        self.snake.push((head.0, head.1));
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0 - 1, head.1));
-
-                        self.food_generator(&buffer, cli);
-                        self.score += 10;
-
-                        self.first_snake_directions.push(Direction::West);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::West);
-                        } */
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            self.snake.push((head.0, head.1));
-                            reversed_vector = self
-                                .snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((buffer.width() - 1, head.1));
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.score += 10;
-
-                            self.first_snake_directions.push(Direction::West);
-                            /*
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::West);
-                            } */
-                        } else {
-                            self.current_direction_first_snake = Still;
-                            reversed_vector = self.snake.clone();
-                            self.finished = true;
-                            if cli.two_players_mode == true {
-                                println!(
-                                    "Player 1 score is {}, Player 2 score is {}",
-                                    self.score, self.second_score
-                                );
-                            } else {
-                                println!("Your score is {}", self.score);
-                            }
-
-                            self.first_snake_directions.push(Direction::Still);
-                            if cli.two_players_mode == true {
-                                self.second_snake_directions.push(Direction::Still);
-                            }
-                        }
-                    }
-                }
-                Direction::Still => {
-                    reversed_vector = self.snake.clone();
-                    self.first_snake_directions.push(Direction::Still);
-                    if cli.two_players_mode == true {
-                        self.second_snake_directions.push(Direction::Still);
-                    }
+        let mut index = 0;
+        while index < self.foods.len() {
+            self.foods[index].age += 1;
+            let item = self.foods[index];
+            if item.runaway && item.age % food::RUNAWAY_TICKS == 0 {
+                self.run_away(index, buffer);
+            }
+            if !settings.expired(item.age) {
+                index += 1;
+            } else if settings.vanishes {
+                self.foods.remove(index);
+            } else {
+                if let Some(cell) = self.free_cell(buffer) {
+                    self.foods[index].position = cell;
                 }
+                self.foods[index].age = 0;
+                index += 1;
             }
-            self.reversed_snake.push(reversed_vector[0].clone());
-            self.snake = reversed_vector;
         }
     }
 
-    pub fn second_snake_update(&mut self, buffer: &WindowBuffer, cli: &Cli) {
-        let snake_collision_check = snakes_collision_checker(&self, cli);
+    /// Steps the food item at `index` one cell away from the closest head,
+    /// unless it's cornered.
+    fn run_away(&mut self, index: usize, buffer: &impl Dimensions) {
+        let position = self.foods[index].position;
+        let Some(head) = [self.head(0), self.head(1)]
+            .into_iter()
+            .flatten()
+            .min_by_key(|head| position.0.abs_diff(head.0) + position.1.abs_diff(head.1))
+        else {
+            return;
+        };
+
+        let cells = food::flee(position, head, buffer.width(), buffer.height());
+        let free = |cell: &(usize, usize)| !self.is_taken(*cell) && self.in_arena(*cell, buffer);
+        if let Some(cell) = cells.into_iter().find(free) {
+            self.foods[index].position = cell;
+        }
+    }
+
+    /// Colour of the bad berry on the board, which tells its kind.
+    pub fn berry_colour(&self, cli: &Cli) -> u32 {
+        self.berries
+            .kinds(cli.two_players_mode)
+            .get(self.berry_kind)
+            .map_or(self.bad_berries_colour, |kind| kind.colour)
+    }
 
-        let mut reversed_vector: Vec<(usize, usize)> = Vec::new();
-        let mut head = (0, 0);
-        let mut snake_body: Vec<(usize, usize)> = Vec::new();
-        if let Some(second_snake) = &self.second_snake {
-            head = second_snake[second_snake.len() - 1];
-            snake_body = second_snake.clone();
+    /// Where a turn taken by `player` really sends their snake.
+    fn steer(&self, player: usize, turn: Direction) -> Direction {
+        if self.effects[player].has(Effect::ReverseControls) {
+            turn.opposite()
+        } else {
+            turn
         }
-        snake_body.pop();
+    }
 
-        let checker = snake_body.iter().any(|(a, b)| (a, b) == (&head.0, &head.1));
-        let mut food_check = (0, 0);
-        if let Some(second_snake) = self.second_snake.clone() {
-            food_check = second_snake[second_snake.len() - 1]
-        }
-
-        if food_check == self.food {
-            match self.current_direction_second_snake {
-                Direction::North => {
-                    if buffer.get(head.0 as isize, head.1 as isize - 1) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        if let Some(mut second_snake) = self.second_snake.clone() {
-                            second_snake.push((head.0, head.1));
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, head.1 - 1));
-                        }
-                        if cli.speed_increase == Difficulty::Hard {
-                            self.snake_speed = 120;
-                        }
-
-                        self.food_generator(&buffer, cli);
-
-                        self.second_score += 10;
-
-                        self.second_snake_directions.push(Direction::North);
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            if let Some(mut second_snake) = self.second_snake.clone() {
-                                second_snake.push((head.0, head.1));
-                                reversed_vector = second_snake
-                                    .windows(2)
-                                    .rev()
-                                    .map(|x| x[1])
-                                    .collect::<Vec<_>>();
-                                reversed_vector = reversed_vector.into_iter().rev().collect();
-                                reversed_vector.push((head.0, buffer.height() - 1));
-                            }
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.second_score += 10;
-
-                            self.second_snake_directions.push(Direction::North);
-                        } else {
-                            self.current_direction_second_snake = Still;
-                            if let Some(second_snake) = self.second_snake.clone() {
-                                reversed_vector = second_snake.clone();
-                            }
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
-                }
-                Direction::South => {
-                    if buffer.get(head.0 as isize, head.1 as isize + 1) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        if let Some(mut second_snake) = self.second_snake.clone() {
-                            second_snake.push((head.0, head.1));
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, head.1 + 1));
-                        }
-
-                        if cli.speed_increase == Difficulty::Hard {
-                            self.snake_speed = 120;
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.second_score += 10;
-
-                        self.second_snake_directions.push(Direction::South);
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            if let Some(mut second_snake) = self.second_snake.clone() {
-                                second_snake.push((head.0, head.1));
-                                reversed_vector = second_snake
-                                    .windows(2)
-                                    .rev()
-                                    .map(|x| x[1])
-                                    .collect::<Vec<_>>();
-                                reversed_vector = reversed_vector.into_iter().rev().collect();
-                                reversed_vector.push((head.0, 0));
-                            }
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.second_score += 10;
-
-                            self.second_snake_directions.push(Direction::South);
-                        } else {
-                            self.current_direction_second_snake = Still;
-                            if let Some(second_snake) = self.second_snake.clone() {
-                                reversed_vector = second_snake.clone();
-                            }
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
-                }
-                Direction::East => {
-                    if buffer.get(head.0 as isize + 1, head.1 as isize) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        if let Some(mut second_snake) = self.second_snake.clone() {
-                            second_snake.push((head.0, head.1));
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0 + 1, head.1));
-                        }
-
-                        if cli.speed_increase == Difficulty::Hard {
-                            self.snake_speed = 120;
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.second_score += 10;
-
-                        self.second_snake_directions.push(Direction::East);
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            if let Some(mut second_snake) = self.second_snake.clone() {
-                                second_snake.push((head.0, head.1));
-                                reversed_vector = second_snake
-                                    .windows(2)
-                                    .rev()
-                                    .map(|x| x[1])
-                                    .collect::<Vec<_>>();
-                                reversed_vector = reversed_vector.into_iter().rev().collect();
-                                reversed_vector.push((0, head.1));
-                            }
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.second_score += 10;
-                            self.second_snake_directions.push(Direction::East);
-                        } else {
-                            self.current_direction_second_snake = Still;
-                            if let Some(second_snake) = self.second_snake.clone() {
-                                reversed_vector = second_snake.clone();
-                            }
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
-                }
-                Direction::West => {
-                    if buffer.get(head.0 as isize - 1, head.1 as isize) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        if let Some(mut second_snake) = self.second_snake.clone() {
-                            second_snake.push((head.0, head.1));
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0 - 1, head.1));
-                        }
-
-                        if cli.speed_increase == Difficulty::Hard {
-                            self.snake_speed = 120;
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.second_score += 10;
-
-                        self.second_snake_directions.push(Direction::West);
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            if let Some(mut second_snake) = self.second_snake.clone() {
-                                second_snake.push((head.0, head.1));
-                                reversed_vector = second_snake
-                                    .windows(2)
-                                    .rev()
-                                    .map(|x| x[1])
-                                    .collect::<Vec<_>>();
-                                reversed_vector = reversed_vector.into_iter().rev().collect();
-                                reversed_vector.push((buffer.width() - 1, head.1))
-                            }
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.second_score += 10;
-
-                            self.second_snake_directions.push(Direction::West);
-                        } else {
-                            self.current_direction_second_snake = Still;
-                            if let Some(second_snake) = self.second_snake.clone() {
-                                reversed_vector = second_snake.clone();
-                            }
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
-                }
-                Direction::Still => {
-                    if let Some(second_snake) = self.second_snake.clone() {
-                        reversed_vector = second_snake.clone();
-                    }
-                    self.second_snake_directions.push(Direction::Still);
+    /// The settings `player`'s snake moves by, in ghost mode while a ghost
+    /// effect runs on it. Invincible and shielded snakes go through walls too.
+    fn cli_for<'a>(&self, player: usize, cli: &'a Cli) -> Cow<'a, Cli> {
+        let effects = &self.effects[player];
+        let through_walls = [Effect::Ghost, Effect::Invincible, Effect::Shielded];
+        if through_walls.iter().any(|effect| effects.has(*effect)) && !cli.ghost_mode {
+            Cow::Owned(Cli {
+                ghost_mode: true,
+                ..cli.clone()
+            })
+        } else {
+            Cow::Borrowed(cli)
+        }
+    }
+
+    /// Starts the timed effects of the bad berry `player` is biting. The rest
+    /// waits for `settle_berry`, once the move is done.
+    fn bite_berry(&mut self, player: usize, cli: &Cli) {
+        let Some(kind) = self
+            .berries
+            .kinds(cli.two_players_mode)
+            .get(self.berry_kind)
+        else {
+            return;
+        };
+        kind.effects
+            .iter()
+            .for_each(|spec| self.effects[player].start(spec));
+
+        let (length, score) = match player {
+            0 => (self.snake.len(), self.score),
+            _ => (
+                self.second_snake.as_ref().map_or(0, Vec::len),
+                self.second_score,
+            ),
+        };
+        self.berry_bites[player] = Some(BerryBite {
+            kind: self.berry_kind,
+            length,
+            score,
+        });
+    }
+
+    /// Bad berries aren't food: takes back the growth and points the move
+    /// gave, then applies the berry's one-off effects.
+    fn settle_berry(&mut self, player: usize, cli: &Cli) {
+        let Some(bite) = self.berry_bites[player].take() else {
+            return;
+        };
+        let Some(kind) = self.berries.kinds(cli.two_players_mode).get(bite.kind) else {
+            return;
+        };
+        let (snake, score) = match player {
+            0 => (&mut self.snake, &mut self.score),
+            _ => match self.second_snake.as_mut() {
+                Some(snake) => (snake, &mut self.second_score),
+                None => return,
+            },
+        };
+
+        if snake.len() > bite.length {
+            snake.remove(0);
+        }
+        *score = bite.score;
+        for spec in &kind.effects {
+            match spec.effect {
+                Effect::Shrink(segments) => {
+                    let cut = segments.min(snake.len().saturating_sub(2));
+                    snake.drain(..cut);
                 }
+                Effect::ScorePenalty(points) => *score = score.saturating_sub(points),
+                _ => (),
             }
-            self.second_snake = Some(reversed_vector);
-        } else if (self.bad_berries_position != None)
-            && food_check == self.bad_berries_position.unwrap()
-        {
-            self.bad_berries += 1;
+        }
+    }
 
-            if self.bad_berries % 2 != 0 {
-                self.snake_speed = self.snake_speed / 3;
-            } else if (self.bad_berries > 1) && (self.bad_berries % 2 == 0) {
-                self.snake_speed = self.snake_speed * 3;
+    /// Ends a two players game as soon as a snake moves into trouble, rather
+    /// than on its next move, so both snakes are judged by the same rules
+    /// whichever one happens to move first.
+    fn settle_versus(
+        &mut self,
+        player: usize,
+        was_finished: bool,
+        ploughed: Option<VersusResult>,
+        buffer: &impl Dimensions,
+        cli: &Cli,
+    ) {
+        if !cli.two_players_mode || self.result.is_some() {
+            return;
+        }
+        let Some(second_snake) = &self.second_snake else {
+            return;
+        };
+        let crashed = (self.finished && !was_finished).then_some(player);
+
+        if let Some(mut result) =
+            ploughed.or_else(|| versus_result(&self.snake, second_snake, crashed))
+        {
+            // Light cycles are judged by who keeps riding, not by length.
+            if cli.tron && result.reason == EndReason::HeadOn {
+                result.winner = None;
+            }
+            let losers = match result.winner {
+                Some(winner) => vec![1 - winner],
+                None => vec![0, 1],
+            };
+            // Shielded snakes go through whatever they meet.
+            if losers.iter().any(|loser| self.is_shielded(*loser)) {
+                return;
+            }
+            if losers.iter().all(|loser| self.lives[*loser] > 0) {
+                losers
+                    .into_iter()
+                    .for_each(|loser| self.lose_life(loser, buffer));
+                self.finished = false;
+                return;
             }
 
-            match self.current_direction_second_snake {
-                Direction::North => {
-                    if buffer.get(head.0 as isize, head.1 as isize - 1) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        if let Some(mut second_snake) = self.second_snake.clone() {
-                            second_snake.push((head.0, head.1));
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, head.1 - 1));
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.second_score += 10;
-
-                        self.second_snake_directions.push(Direction::North);
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            if let Some(mut second_snake) = self.second_snake.clone() {
-                                second_snake.push((head.0, head.1));
-                                reversed_vector = second_snake
-                                    .windows(2)
-                                    .rev()
-                                    .map(|x| x[1])
-                                    .collect::<Vec<_>>();
-                                reversed_vector = reversed_vector.into_iter().rev().collect();
-                                reversed_vector.push((head.0, buffer.height() - 1));
-                            }
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.second_score += 10;
-
-                            self.second_snake_directions.push(Direction::North);
-                        } else {
-                            self.current_direction_second_snake = Still;
-                            if let Some(second_snake) = self.second_snake.clone() {
-                                reversed_vector = second_snake.clone();
-                            }
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
-                }
-                Direction::South => {
-                    if buffer.get(head.0 as isize, head.1 as isize + 1) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        if let Some(mut second_snake) = self.second_snake.clone() {
-                            second_snake.push((head.0, head.1));
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, head.1 + 1));
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.second_score += 10;
-
-                        self.second_snake_directions.push(Direction::South);
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            if let Some(mut second_snake) = self.second_snake.clone() {
-                                second_snake.push((head.0, head.1));
-                                reversed_vector = second_snake
-                                    .windows(2)
-                                    .rev()
-                                    .map(|x| x[1])
-                                    .collect::<Vec<_>>();
-                                reversed_vector = reversed_vector.into_iter().rev().collect();
-                                reversed_vector.push((head.0, 0));
-                            }
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.second_score += 10;
-
-                            self.second_snake_directions.push(Direction::South);
-                        } else {
-                            self.current_direction_second_snake = Still;
-                            if let Some(second_snake) = self.second_snake.clone() {
-                                reversed_vector = second_snake.clone();
-                            }
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
-                }
-                Direction::East => {
-                    if buffer.get(head.0 as isize + 1, head.1 as isize) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        if let Some(mut second_snake) = self.second_snake.clone() {
-                            second_snake.push((head.0, head.1));
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0 + 1, head.1));
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.second_score += 10;
-
-                        self.second_snake_directions.push(Direction::East);
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            if let Some(mut second_snake) = self.second_snake.clone() {
-                                second_snake.push((head.0, head.1));
-                                reversed_vector = second_snake
-                                    .windows(2)
-                                    .rev()
-                                    .map(|x| x[1])
-                                    .collect::<Vec<_>>();
-                                reversed_vector = reversed_vector.into_iter().rev().collect();
-                                reversed_vector.push((0, head.1));
-                            }
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.second_score += 10;
-
-                            self.second_snake_directions.push(Direction::East);
-                        } else {
-                            self.current_direction_second_snake = Still;
-                            if let Some(second_snake) = self.second_snake.clone() {
-                                reversed_vector = second_snake.clone();
-                            }
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
-                }
-                Direction::West => {
-                    if buffer.get(head.0 as isize - 1, head.1 as isize) != None
-                        && checker == false
-                        && snake_collision_check == false
-                    {
-                        if let Some(mut second_snake) = self.second_snake.clone() {
-                            second_snake.push((head.0, head.1));
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0 - 1, head.1));
-                        }
-
-                        self.food_generator(&buffer, cli);
-                        self.second_score += 10;
-
-                        self.second_snake_directions.push(Direction::West);
-                    } else {
-                        if cli.ghost_mode == true
-                            && checker == false
-                            && snake_collision_check == false
-                        {
-                            if let Some(mut second_snake) = self.second_snake.clone() {
-                                second_snake.push((head.0, head.1));
-                                reversed_vector = second_snake
-                                    .windows(2)
-                                    .rev()
-                                    .map(|x| x[1])
-                                    .collect::<Vec<_>>();
-                                reversed_vector = reversed_vector.into_iter().rev().collect();
-                                reversed_vector.push((buffer.width() - 1, head.1));
-                            }
-
-                            if cli.speed_increase == Difficulty::Hard {
-                                self.snake_speed = 120;
-                            }
-
-                            self.food_generator(&buffer, cli);
-
-                            self.second_score += 10;
-
-                            self.second_snake_directions.push(Direction::West);
-                        } else {
-                            self.current_direction_second_snake = Still;
-                            if let Some(second_snake) = self.second_snake.clone() {
-                                reversed_vector = second_snake.clone();
-                            }
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
-                }
-                Direction::Still => {
-                    if let Some(second_snake) = self.second_snake.clone() {
-                        reversed_vector = second_snake.clone();
-                    }
-                    self.second_snake_directions.push(Direction::Still);
+            self.current_direction_first_snake = Still;
+            self.current_direction_second_snake = Still;
+            self.finished = true;
+            self.result = Some(result);
+        }
+    }
+
+    /// Spends one of `player`'s extra lives to put their snake back on a
+    /// safe spot, where it waits a few moves before setting off shielded.
+    /// It starts over standing still in its spawn heading, so turns are
+    /// judged against that rather than the way it was going when it crashed.
+    /// The other snake plays on meanwhile.
+    fn lose_life(&mut self, player: usize, buffer: &impl Dimensions) {
+        self.lives[player] -= 1;
+        let spawn = self.safe_spawn(player, buffer);
+        if player == 0 {
+            self.snake = spawn;
+            self.current_direction_first_snake = Still;
+            self.first_snake_turns.clear();
+            self.first_snake_directions = vec![Still];
+        } else {
+            self.second_snake = Some(spawn);
+            self.current_direction_second_snake = Still;
+            self.second_snake_turns.clear();
+            self.second_snake_directions = vec![Still];
+        }
+
+        self.effects[player] = Effects::default();
+        self.effects[player].start(&EffectSpec {
+            effect: Effect::Shielded,
+            moves: RESPAWN_SHIELD,
+            stacking: Stacking::Refresh,
+        });
+        self.respawn_wait[player] = RESPAWN_DELAY;
+    }
+
+    /// Where `player`'s snake comes back: where it started, or the closest
+    /// row to it with nothing in the way.
+    fn safe_spawn(&self, player: usize, buffer: &impl Dimensions) -> Vec<(usize, usize)> {
+        let spawn = &self.spawns[player];
+        let other = match player {
+            0 => self.second_snake.as_deref().unwrap_or_default(),
+            _ => &self.snake,
+        };
+        let free = |cell: &(usize, usize)| {
+            self.in_arena(*cell, buffer) && !other.contains(cell) && !self.is_portal(*cell)
+        };
+
+        for offset in 0..buffer.height() {
+            for shift in [offset as isize, -(offset as isize)] {
+                let moved: Option<Vec<_>> = spawn
+                    .iter()
+                    .map(|(x, y)| y.checked_add_signed(shift).map(|y| (*x, y)))
+                    .collect();
+                if let Some(moved) = moved.filter(|moved| moved.iter().all(free)) {
+                    return moved;
                 }
             }
+        }
+        spawn.clone()
+    }
 
-            if let Some(mut reversed_second_snake) = self.reversed_second_snake.clone() {
-                reversed_second_snake.push(reversed_vector[0].clone());
+    /// Counts down the moves `player`'s snake waits after losing a life,
+    /// setting it off the way it started once they are over. Returns whether
+    /// it's still waiting, which means it doesn't move.
+    fn wait_to_respawn(&mut self, player: usize) -> bool {
+        if self.respawn_wait[player] == 0 {
+            return false;
+        }
+        self.respawn_wait[player] -= 1;
+        if self.respawn_wait[player] == 0 {
+            match player {
+                0 => self.current_direction_first_snake = Direction::East,
+                _ => self.current_direction_second_snake = Direction::West,
             }
-            if let Some(mut _second_snake_body) = self.second_snake.clone() {
-                self.second_snake = Some(reversed_vector);
+        }
+        true
+    }
+
+    fn is_shielded(&self, player: usize) -> bool {
+        self.effects[player].has(Effect::Shielded)
+    }
+
+    /// Whether a snake's head is on the other snake's body, leaving out
+    /// shielded snakes, which go through.
+    fn snakes_collide(&self, cli: &Cli) -> bool {
+        if !self.is_shielded(0) && !self.is_shielded(1) {
+            return snakes_collision_checker(self, cli);
+        }
+        let (Some(first), Some(second)) = (self.head(0), self.head(1)) else {
+            return false;
+        };
+        let second_snake = self.second_snake.as_deref().unwrap_or_default();
+        let body = |snake: &[(usize, usize)]| snake[..snake.len() - 1].to_vec();
+
+        cli.two_players_mode
+            && ((!self.is_shielded(0) && body(second_snake).contains(&first))
+                || (!self.is_shielded(1) && body(&self.snake).contains(&second)))
+    }
+
+    /// Hash of everything that decides how the game goes on, for checking
+    /// that two copies of a game are still in step.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+
+        self.snake.hash(&mut hasher);
+        self.second_snake.hash(&mut hasher);
+        self.food.hash(&mut hasher);
+        self.bad_berries_position.hash(&mut hasher);
+        self.bad_berries.hash(&mut hasher);
+        self.score.hash(&mut hasher);
+        self.second_score.hash(&mut hasher);
+        self.snake_speed.hash(&mut hasher);
+        self.second_snake_speed.hash(&mut hasher);
+        self.lives.hash(&mut hasher);
+        self.respawn_wait.hash(&mut hasher);
+        self.berry_kind.hash(&mut hasher);
+        self.effects.hash(&mut hasher);
+        self.foods.hash(&mut hasher);
+        self.food_ticks.hash(&mut hasher);
+        self.food_age.hash(&mut hasher);
+        self.power_up.hash(&mut hasher);
+        self.portals.hash(&mut hasher);
+        self.border.hash(&mut hasher);
+        self.shrink_elapsed.hash(&mut hasher);
+        self.finished.hash(&mut hasher);
+        self.foods_spawned.hash(&mut hasher);
+        self.result.hash(&mut hasher);
+        self.current_direction_first_snake.hash(&mut hasher);
+        self.current_direction_second_snake.hash(&mut hasher);
+        self.first_snake_turns.hash(&mut hasher);
+        self.second_snake_turns.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    /// Interval between ticks for each player, `None` while that player
+    /// shouldn't be ticked at all. Meant for `GameLoop::next_due`.
+    pub fn tick_intervals(&self, cli: &Cli) -> Vec<Option<Duration>> {
+        let players = if cli.two_players_mode { 2 } else { 1 };
+        let speeds = [
+            self.effects[0].speed(self.snake_speed),
+            self.effects[1].speed(self.second_snake_speed),
+        ];
+
+        match self.time_cycle {
+            TimeCycle::Forward if !self.finished && self.pause == PauseState::Running => speeds
+                [..players]
+                .iter()
+                .map(|speed| Some(Duration::from_millis(*speed as u64)))
+                .collect(),
+            TimeCycle::Backward => {
+                let mut intervals = vec![None; players];
+                intervals[0] = Some(REWIND_SPEED);
+                intervals
             }
+            _ => vec![None; players],
         }
     }
 
-    pub fn direction(&mut self, buffer: &WindowBuffer, cli: &Cli) {
-        let snake_collision_check = snakes_collision_checker(&self, cli);
-        let mut reversed_vector: Vec<(usize, usize)> = Vec::new();
-        let head = self.snake[self.snake.len() - 1];
-        let mut snake_body = self.snake.clone();
-        snake_body.pop();
+    /// One frame of the game loop: follows the window's focus, then runs
+    /// every tick that is due by `now`. Returns how a two players game ended
+    /// if it ended during this frame.
+    pub fn play_frame(
+        &mut self,
+        focused: bool,
+        game_loop: &mut GameLoop,
+        now: Instant,
+        buffer: &impl Dimensions,
+        cli: &Cli,
+    ) -> Option<VersusResult> {
+        let settled = self.result.is_some();
+        self.set_focus(focused);
+        game_loop.elapse(now);
+        while let Some(player) = game_loop.next_due(&self.tick_intervals(cli)) {
+            self.tick(player, buffer, cli);
+        }
+        self.result.filter(|_| !settled)
+    }
 
-        let checker = snake_body.iter().any(|(a, b)| (a, b) == (&head.0, &head.1));
+    /// Runs one tick for `player`, as scheduled by the game loop.
+    pub fn tick(&mut self, player: usize, buffer: &impl Dimensions, cli: &Cli) {
+        match self.time_cycle {
+            TimeCycle::Forward if player == 0 => self.update_first_snake(buffer, cli),
+            TimeCycle::Forward => self.update_second_snake(buffer, cli),
+            TimeCycle::Backward => {
+                return_in_time(self, cli);
+                self.time_cycle = TimeCycle::Pause;
+            }
+            TimeCycle::Pause => (),
+        }
+    }
 
-        match self.current_direction_first_snake {
-            Direction::North => {
-                if buffer.get(head.0 as isize, head.1 as isize - 1) != None
-                    && checker == false
-                    && snake_collision_check == false
-                {
-                    reversed_vector = self
-                        .snake
-                        .windows(2)
-                        .rev()
-                        .map(|x| x[1])
-                        .collect::<Vec<_>>();
-                    reversed_vector = reversed_vector.into_iter().rev().collect();
-                    reversed_vector.push((head.0, head.1 - 1));
-                    if cli.speed_increase == Difficulty::Hard && self.snake_speed > 0 {
-                        self.snake_speed -= 1;
-                    }
+    pub fn reset(&mut self) {
+        self.snake.clear();
+        self.result = None;
+        self.first_snake_turns.clear();
+        self.second_snake_turns.clear();
+        if let Some(mut pos) = self.second_snake.clone() {
+            pos.clear();
+        }
+    }
 
-                    self.first_snake_directions.push(Direction::North);
-                    /*
-                    if cli.two_players_mode == true {
-                        self.second_snake_directions.push(Direction::North);
-                    } */
-                } else {
-                    if cli.ghost_mode == true && checker == false && snake_collision_check == false
-                    {
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0, buffer.height() - 1));
-
-                        self.first_snake_directions.push(Direction::North);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::North);
-                        } */
-                    } else {
-                        self.current_direction_first_snake = Still;
-                        reversed_vector = self.snake.clone();
-                        self.finished = true;
-                        if cli.two_players_mode == true {
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-                        } else {
-                            println!("Your score is {}", self.score);
-                        }
-
-                        self.first_snake_directions.push(Direction::Still);
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
+    pub fn food_generator(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        loop {
+            let x = self.rng.gen_range(0..buffer.width());
+            let y = self.rng.gen_range(0..buffer.height());
+            let v: usize = self.rng.gen_range(0..buffer.width());
+            let w: usize = self.rng.gen_range(0..buffer.height());
+
+            let blocked = |cell| self.is_portal(cell) || !self.in_arena(cell, buffer);
+            let checker_1 = self.snake.iter().any(|(a, b)| (a, b) == (&x, &y)) || blocked((x, y));
+            let checker_2 = self.snake.iter().any(|(a, b)| (a, b) == (&v, &w)) || blocked((v, w));
+
+            if checker_1 || (x == v && y == w) || checker_2 {
+                continue;
+            } else {
+                self.food = (x, y);
+                self.food_age = 0;
+                // Light cycles have nothing to eat, the food stays hidden.
+                if cli.tron {
+                    self.bad_berries_position = None;
+                    return;
                 }
-            }
-            Direction::South => {
-                if buffer.get(head.0 as isize, head.1 as isize + 1) != None
-                    && checker == false
-                    && snake_collision_check == false
-                {
-                    reversed_vector = self
-                        .snake
-                        .windows(2)
-                        .rev()
-                        .map(|x| x[1])
-                        .collect::<Vec<_>>();
-                    reversed_vector = reversed_vector.into_iter().rev().collect();
-                    reversed_vector.push((head.0, head.1 + 1));
-                    if cli.speed_increase == Difficulty::Hard && self.snake_speed > 0 {
-                        self.snake_speed -= 1;
+                if cli.bad_berries && self.difficulty.spawns_bad_berry(self.foods_spawned) {
+                    self.bad_berries_position = Some((v, w));
+                    let kinds = self.berries.kinds(cli.two_players_mode).len();
+                    if kinds > 1 {
+                        self.berry_kind = self.rng.gen_range(0..kinds);
                     }
-
-                    self.first_snake_directions.push(Direction::South);
-                    /*
-                    if cli.two_players_mode == true {
-                        self.second_snake_directions.push(Direction::South);
-                    }*/
                 } else {
-                    if cli.ghost_mode == true && checker == false && snake_collision_check == false
-                    {
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0, 0));
-                        self.first_snake_directions.push(Direction::South);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::South);
-                        } */
-                    } else {
-                        self.current_direction_first_snake = Still;
-                        reversed_vector = self.snake.clone();
-                        self.finished = true;
-                        if cli.two_players_mode == true {
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-                        } else {
-                            println!("Your score is {}", self.score);
-                        }
-
-                        self.first_snake_directions.push(Direction::Still);
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
+                    self.bad_berries_position = None;
                 }
-            }
-            Direction::East => {
-                if buffer.get(head.0 as isize + 1, head.1 as isize) != None
-                    && checker == false
-                    && snake_collision_check == false
+                self.foods_spawned += 1;
+                if FoodSettings::from_cli(cli).spawn_ticks.is_none() {
+                    self.top_up_foods(buffer, cli);
+                }
+                if cli.power_ups
+                    && self.power_up.is_none()
+                    && self.rng.gen_ratio(1, POWER_UP_CHANCE)
                 {
-                    reversed_vector = self
-                        .snake
-                        .windows(2)
-                        .rev()
-                        .map(|x| x[1])
-                        .collect::<Vec<_>>();
-                    reversed_vector = reversed_vector.into_iter().rev().collect();
-                    reversed_vector.push((head.0 + 1, head.1));
-                    if cli.speed_increase == Difficulty::Hard && self.snake_speed > 0 {
-                        self.snake_speed -= 1;
-                    }
-
-                    self.first_snake_directions.push(Direction::East);
-                    /*
-                    if cli.two_players_mode == true {
-                        self.second_snake_directions.push(Direction::East);
-                    } */
-                } else {
-                    if cli.ghost_mode == true && checker == false && snake_collision_check == false
-                    {
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((0, head.1));
-
-                        self.first_snake_directions.push(Direction::East);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::East);
-                        }  */
-                    } else {
-                        self.current_direction_first_snake = Still;
-                        reversed_vector = self.snake.clone();
-                        self.finished = true;
-                        if cli.two_players_mode == true {
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-                        } else {
-                            println!("Your score is {}", self.score);
-                        }
-
-                        self.first_snake_directions.push(Direction::Still);
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::Still);
-                        }
+                    if let Some(position) = self.free_cell(buffer) {
+                        self.power_up = Some(PowerUp::random(position, &mut self.rng));
                     }
                 }
+                return;
             }
-            Direction::West => {
-                if buffer.get(head.0 as isize - 1, head.1 as isize) != None
-                    && checker == false
-                    && snake_collision_check == false
-                {
-                    reversed_vector = self
-                        .snake
-                        .windows(2)
-                        .rev()
-                        .map(|x| x[1])
-                        .collect::<Vec<_>>();
-                    reversed_vector = reversed_vector.into_iter().rev().collect();
-                    reversed_vector.push((head.0 - 1, head.1));
-                    if cli.speed_increase == Difficulty::Hard && self.snake_speed > 0 {
-                        self.snake_speed -= 1;
-                    }
+        }
+    }
 
-                    self.first_snake_directions.push(Direction::West);
-                    /*
-                    if cli.two_players_mode == true {
-                        self.second_snake_directions.push(Direction::West);
-                    }*/
-                } else {
-                    if cli.ghost_mode == true && checker == false && snake_collision_check == false
-                    {
-                        reversed_vector = self
-                            .snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((buffer.width() - 1, head.1));
-
-                        self.first_snake_directions.push(Direction::West);
-                        /*
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::West);
-                        }  */
-                    } else {
-                        self.current_direction_first_snake = Still;
-                        reversed_vector = self.snake.clone();
-                        self.finished = true;
-                        if cli.two_players_mode == true {
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-                        } else {
-                            println!("Your score is {}", self.score);
-                        }
-
-                        self.first_snake_directions.push(Direction::Still);
-                        if cli.two_players_mode == true {
-                            self.second_snake_directions.push(Direction::Still);
-                        }
-                    }
-                }
+    #[cfg(feature = "gui")]
+    pub fn handle_user_input<W: Graphic>(
+        &mut self,
+        window: &W,
+        cli: &Cli,
+        buffer: &impl Dimensions,
+    ) -> std::io::Result<()> {
+        if window.is_key_pressed(graphic::Key::Quit) {
+            self.reset();
+        }
+
+        if window.is_key_pressed(graphic::Key::Save) {
+            let mut save_file = File::create("save_file")?;
+
+            if cli.file_path != None {
+                save_file = File::create(cli.file_path.clone().unwrap())?;
             }
-            Direction::Still => {
-                self.current_direction_first_snake = Still;
-                reversed_vector = self.snake.clone();
-                self.first_snake_directions.push(Direction::Still);
-                if cli.two_players_mode == true {
-                    self.second_snake_directions.push(Direction::Still);
-                }
+            save_file.write_all(&buffer.width().to_be_bytes())?;
+            save_file.write_all(&buffer.height().to_be_bytes())?;
+            save_file.write_all(&self.food.0.to_be_bytes())?;
+            save_file.write_all(&self.food.1.to_be_bytes())?;
+
+            /*save_file.write_all(&self.speed().to_be_bytes())?;
+            save_file.write_all(&self.snake.to_be_bytes())?;
+
+            for number in &self.snake {
+                save_file.write_all(&number.to_be_bytes())?;
+            } */
+
+            save_file.flush()?;
+        }
+
+        if window.is_key_pressed(graphic::Key::Up) {
+            self.time_cycle = TimeCycle::Forward;
+            self.turn_first_snake(Direction::North);
+        }
+
+        if window.is_key_pressed(graphic::Key::Down) {
+            self.time_cycle = TimeCycle::Forward;
+            self.turn_first_snake(Direction::South);
+        }
+
+        if window.is_key_pressed(graphic::Key::Left) {
+            self.time_cycle = TimeCycle::Forward;
+            self.turn_first_snake(Direction::West);
+        }
+
+        if window.is_key_pressed(graphic::Key::Right) {
+            self.time_cycle = TimeCycle::Forward;
+            self.turn_first_snake(Direction::East);
+        }
+
+        if cli.two_players_mode {
+            if window.is_key_pressed(graphic::Key::UpPlayer2) {
+                self.turn_second_snake(Direction::North);
+            }
+
+            if window.is_key_pressed(graphic::Key::DownPlayer2) {
+                self.turn_second_snake(Direction::South);
+            }
+
+            if window.is_key_pressed(graphic::Key::LeftPlayer2) {
+                self.turn_second_snake(Direction::West);
+            }
+
+            if window.is_key_pressed(graphic::Key::RightPlayer2) {
+                self.turn_second_snake(Direction::East);
             }
         }
-        self.reversed_snake.push(reversed_vector[0].clone());
-        self.snake = reversed_vector;
-    }
 
-    pub fn direction_second_snake(&mut self, buffer: &WindowBuffer, cli: &Cli) {
-        let snake_collision_check = snakes_collision_checker(&self, cli);
-        let mut reversed_vector: Vec<(usize, usize)> = Vec::new();
-        let mut head = (0, 0);
-        let mut snake_body: Vec<(usize, usize)> = Vec::new();
-        if let Some(second_snake) = &self.second_snake {
-            head = second_snake[second_snake.len() - 1];
-            snake_body = second_snake.clone();
+        if window.get_keys_released().contains(&graphic::Key::Space) {
+            self.toggle_pause();
         }
-        snake_body.pop();
+        self.update_pause();
 
-        let checker = snake_body.iter().any(|(a, b)| (a, b) == (&head.0, &head.1));
+        if window.is_key_pressed(graphic::Key::Backward) {
+            self.time_cycle = TimeCycle::Backward;
+        }
 
-        match self.current_direction_second_snake {
-            Direction::North => {
-                if buffer.get(head.0 as isize, head.1 as isize - 1) != None
-                    && checker == false
-                    && snake_collision_check == false
-                {
-                    if let Some(second_snake) = self.second_snake.clone() {
-                        reversed_vector = second_snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0, head.1 - 1));
-                    }
+        if window.is_key_pressed(graphic::Key::Forward) {
+            self.time_cycle = TimeCycle::Forward;
+        }
 
-                    self.second_snake_directions.push(Direction::North);
-                } else {
-                    if cli.ghost_mode == true && checker == false && snake_collision_check == false
-                    {
-                        if let Some(second_snake) = self.second_snake.clone() {
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, buffer.height() - 1));
-
-                            if cli.speed_increase == Difficulty::Hard && self.snake_speed > 0 {
-                                self.snake_speed -= 1;
-                            }
-                        }
-
-                        self.second_snake_directions.push(Direction::North);
-                    } else {
-                        self.current_direction_second_snake = Still;
-
-                        if let Some(second_snake) = self.second_snake.clone() {
-                            reversed_vector = second_snake.clone();
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-                        }
-
-                        self.second_snake_directions.push(Direction::Still);
-                    }
-                }
+        Ok(())
+    }
+
+    /// Pauses a running game, or starts the countdown to resume a paused one.
+    pub fn toggle_pause(&mut self) {
+        self.pause = match self.pause {
+            PauseState::Running | PauseState::Resuming(_) => PauseState::Paused,
+            PauseState::Paused => PauseState::Resuming(self.clock.now() + RESUME_COUNTDOWN),
+        };
+    }
+
+    /// Pauses the game when the window loses focus. Getting it back doesn't
+    /// resume on its own, the player does that when ready.
+    pub fn set_focus(&mut self, focused: bool) {
+        if !focused {
+            self.pause = PauseState::Paused;
+        }
+    }
+
+    /// Ends the resume countdown once it has run out.
+    pub fn update_pause(&mut self) {
+        if let PauseState::Resuming(until) = self.pause {
+            if self.clock.now() >= until {
+                self.pause = PauseState::Running;
             }
-            Direction::South => {
-                if buffer.get(head.0 as isize, head.1 as isize + 1) != None
-                    && checker == false
-                    && snake_collision_check == false
-                {
-                    if let Some(second_snake) = self.second_snake.clone() {
-                        reversed_vector = second_snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0, head.1 + 1));
-                        if cli.speed_increase == Difficulty::Hard && self.snake_speed > 0 {
-                            self.snake_speed -= 1;
-                        }
-                    }
+        }
+    }
 
-                    self.second_snake_directions.push(Direction::South);
-                } else {
-                    if cli.ghost_mode == true && checker == false && snake_collision_check == false
-                    {
-                        if let Some(second_snake) = self.second_snake.clone() {
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((head.0, 0));
-                            if cli.speed_increase == Difficulty::Hard && self.snake_speed > 0 {
-                                self.snake_speed -= 1;
-                            }
-                        }
-                        self.second_snake_directions.push(Direction::South);
-                    } else {
-                        self.current_direction_second_snake = Still;
-
-                        if let Some(second_snake) = self.second_snake.clone() {
-                            reversed_vector = second_snake.clone();
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-                        }
-
-                        self.second_snake_directions.push(Direction::Still);
-                    }
+    /// Buffers a turn for the first snake, applied on a later tick by `update`.
+    pub fn turn_first_snake(&mut self, direction: Direction) {
+        queue_turn(
+            &mut self.first_snake_turns,
+            &self.first_snake_directions,
+            Direction::East,
+            direction,
+        );
+    }
+
+    /// Buffers a turn for the second snake, applied on a later tick by `update`.
+    pub fn turn_second_snake(&mut self, direction: Direction) {
+        queue_turn(
+            &mut self.second_snake_turns,
+            &self.second_snake_directions,
+            Direction::West,
+            direction,
+        );
+    }
+
+    pub fn snake_update(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        let snake_collision_check = self.snakes_collide(cli);
+
+        let head = self.snake[self.snake.len() - 1];
+        let mut snake_body = self.snake.clone();
+        snake_body.pop();
+        let checker =
+            snake_body.iter().any(|(a, b)| (a, b) == (&head.0, &head.1)) && !self.is_shielded(0);
+
+        let eats_food = head == self.food;
+        if cli.tron || (!eats_food && self.bad_berries_position != Some(head)) {
+            return;
+        }
+        if !eats_food {
+            self.bad_berries += 1;
+            self.bite_berry(0, cli);
+        }
+
+        let direction = self.current_direction_first_snake.clone();
+        let next = self.step(head, &direction, buffer, cli);
+        let reversed_vector = match next {
+            _ if direction == Still => {
+                self.first_snake_directions.push(Direction::Still);
+                self.snake.clone()
+            }
+            Some(next) if !checker && !snake_collision_check => {
+                let reversed_vector = moved_snake(&self.snake, next, true);
+                if eats_food {
+                    self.snake_speed = cli
+                        .handicap(0)
+                        .speed_after_food(&self.difficulty, self.snake_speed);
                 }
+                self.food_generator(buffer, cli);
+                self.score += self.difficulty.food_score();
+                self.first_snake_directions.push(direction);
+                reversed_vector
             }
-            Direction::East => {
-                if buffer.get(head.0 as isize + 1, head.1 as isize) != None
-                    && checker == false
-                    && snake_collision_check == false
-                {
-                    if let Some(second_snake) = self.second_snake.clone() {
-                        reversed_vector = second_snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0 + 1, head.1));
-                        if cli.speed_increase == Difficulty::Hard && self.snake_speed > 0 {
-                            self.snake_speed -= 1;
-                        }
-                    }
+            _ => {
+                self.first_snake_crashed(cli);
+                self.snake.clone()
+            }
+        };
 
-                    self.second_snake_directions.push(Direction::East);
-                } else {
-                    if cli.ghost_mode == true && checker == false && snake_collision_check == false
-                    {
-                        if let Some(second_snake) = self.second_snake.clone() {
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((0, head.1));
-                        }
-
-                        self.second_snake_directions.push(Direction::East);
-                    } else {
-                        self.current_direction_second_snake = Still;
-
-                        if let Some(second_snake) = self.second_snake.clone() {
-                            reversed_vector = second_snake.clone();
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-                        }
-
-                        self.second_snake_directions.push(Direction::Still);
-                    }
+        if !eats_food {
+            self.reversed_snake.push(reversed_vector[0]);
+        }
+        self.snake = reversed_vector;
+    }
+
+    pub fn second_snake_update(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        let snake_collision_check = self.snakes_collide(cli);
+
+        let Some(second_snake) = self.second_snake.clone() else {
+            return;
+        };
+        let head = second_snake[second_snake.len() - 1];
+        let mut snake_body = second_snake.clone();
+        snake_body.pop();
+        let checker =
+            snake_body.iter().any(|(a, b)| (a, b) == (&head.0, &head.1)) && !self.is_shielded(1);
+
+        let eats_food = head == self.food;
+        if cli.tron || (!eats_food && self.bad_berries_position != Some(head)) {
+            return;
+        }
+        if !eats_food {
+            self.second_bad_berries += 1;
+            self.bite_berry(1, cli);
+        }
+
+        let direction = self.current_direction_second_snake.clone();
+        let next = self.step(head, &direction, buffer, cli);
+        let reversed_vector = match next {
+            _ if direction == Still => {
+                self.second_snake_directions.push(Direction::Still);
+                second_snake
+            }
+            Some(next) if !checker && !snake_collision_check => {
+                let reversed_vector = moved_snake(&second_snake, next, true);
+                if eats_food {
+                    self.second_snake_speed = cli
+                        .handicap(1)
+                        .speed_after_food(&self.difficulty, self.second_snake_speed);
                 }
+                self.food_generator(buffer, cli);
+                self.second_score += self.difficulty.food_score();
+                self.second_snake_directions.push(direction);
+                reversed_vector
             }
-            Direction::West => {
-                if buffer.get(head.0 as isize - 1, head.1 as isize) != None
-                    && checker == false
-                    && snake_collision_check == false
-                {
-                    if let Some(second_snake) = self.second_snake.clone() {
-                        reversed_vector = second_snake
-                            .windows(2)
-                            .rev()
-                            .map(|x| x[1])
-                            .collect::<Vec<_>>();
-                        reversed_vector = reversed_vector.into_iter().rev().collect();
-                        reversed_vector.push((head.0 - 1, head.1));
-                        if cli.speed_increase == Difficulty::Hard && self.snake_speed > 0 {
-                            self.snake_speed -= 1;
-                        }
-                    }
+            _ => {
+                self.second_snake_crashed();
+                second_snake
+            }
+        };
 
-                    self.second_snake_directions.push(Direction::West);
-                } else {
-                    if cli.ghost_mode == true && checker == false && snake_collision_check == false
-                    {
-                        if let Some(second_snake) = self.second_snake.clone() {
-                            reversed_vector = second_snake
-                                .windows(2)
-                                .rev()
-                                .map(|x| x[1])
-                                .collect::<Vec<_>>();
-                            reversed_vector = reversed_vector.into_iter().rev().collect();
-                            reversed_vector.push((buffer.width() - 1, head.1));
-                        }
-
-                        self.second_snake_directions.push(Direction::West);
-                    } else {
-                        self.current_direction_second_snake = Still;
-
-                        if let Some(second_snake) = self.second_snake.clone() {
-                            reversed_vector = second_snake.clone();
-                            self.finished = true;
-                            println!(
-                                "Player 1 score is {}, Player 2 score is {}",
-                                self.score, self.second_score
-                            );
-                        }
-
-                        self.second_snake_directions.push(Direction::Still);
-                    }
+        self.second_snake = Some(reversed_vector);
+    }
+
+    pub fn direction(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        let snake_collision_check = self.snakes_collide(cli);
+        let head = self.snake[self.snake.len() - 1];
+        let mut snake_body = self.snake.clone();
+        snake_body.pop();
+
+        let checker =
+            snake_body.iter().any(|(a, b)| (a, b) == (&head.0, &head.1)) && !self.is_shielded(0);
+
+        let direction = self.current_direction_first_snake.clone();
+        let next = self.step(head, &direction, buffer, cli);
+        let reversed_vector = match next {
+            _ if direction == Still => {
+                self.first_snake_directions.push(Direction::Still);
+                if cli.two_players_mode {
+                    self.second_snake_directions.push(Direction::Still);
                 }
+                self.snake.clone()
             }
-            Direction::Still => {
-                if let Some(second_snake) = self.second_snake.clone() {
-                    reversed_vector = second_snake.clone();
+            Some(next) if !checker && !snake_collision_check => {
+                self.first_snake_directions.push(direction);
+                moved_snake(&self.snake, next, false)
+            }
+            _ => {
+                self.first_snake_crashed(cli);
+                if cli.two_players_mode {
+                    self.second_snake_directions.push(Direction::Still);
                 }
+                self.snake.clone()
+            }
+        };
+        self.reversed_snake.push(reversed_vector[0]);
+        self.snake = reversed_vector;
+    }
+
+    pub fn direction_second_snake(&mut self, buffer: &impl Dimensions, cli: &Cli) {
+        let snake_collision_check = self.snakes_collide(cli);
+        let Some(second_snake) = self.second_snake.clone() else {
+            return;
+        };
+        let head = second_snake[second_snake.len() - 1];
+        let mut snake_body = second_snake.clone();
+        snake_body.pop();
+
+        let checker =
+            snake_body.iter().any(|(a, b)| (a, b) == (&head.0, &head.1)) && !self.is_shielded(1);
+
+        let direction = self.current_direction_second_snake.clone();
+        let next = self.step(head, &direction, buffer, cli);
+        let reversed_vector = match next {
+            _ if direction == Still => {
                 self.second_snake_directions.push(Direction::Still);
+                second_snake
             }
+            Some(next) if !checker && !snake_collision_check => {
+                self.second_snake_directions.push(direction);
+                moved_snake(&second_snake, next, false)
+            }
+            _ => {
+                self.second_snake_crashed();
+                second_snake
+            }
+        };
+        self.second_snake = Some(reversed_vector);
+    }
+
+    /// Stops the first snake, which ran into something, and ends the game.
+    fn first_snake_crashed(&mut self, cli: &Cli) {
+        self.current_direction_first_snake = Still;
+        self.finished = true;
+        if cli.two_players_mode {
+            println!(
+                "Player 1 score is {}, Player 2 score is {}",
+                self.score, self.second_score
+            );
+        } else {
+            println!("Your score is {}", self.score);
         }
-        if let Some(mut reversed_second_snake) = self.reversed_second_snake.clone() {
-            reversed_second_snake.push(reversed_vector[0].clone());
-        }
-        if let Some(_second_snake_body) = self.second_snake.clone() {
-            self.second_snake = Some(reversed_vector);
-        }
+        self.first_snake_directions.push(Direction::Still);
+    }
+
+    /// Stops the second snake, which ran into something, and ends the game.
+    fn second_snake_crashed(&mut self) {
+        self.current_direction_second_snake = Still;
+        self.finished = true;
+        println!(
+            "Player 1 score is {}, Player 2 score is {}",
+            self.score, self.second_score
+        );
+        self.second_snake_directions.push(Direction::Still);
+    }
+}
+
+/// The cells of `snake` once its head has moved on to `next`, the rest
+/// following. When it `grows`, the cell the head left is kept twice, which
+/// makes the snake one cell longer.
+fn moved_snake(snake: &[(usize, usize)], next: (usize, usize), grows: bool) -> Vec<(usize, usize)> {
+    let mut moved: Vec<_> = snake.iter().skip(1).copied().collect();
+    if grows {
+        moved.extend(snake.last());
     }
+    moved.push(next);
+    moved
 }
 
-#[cfg(test)]
+// Nearly every test plays a `World`.
+#[cfg(all(test, feature = "gui"))]
 mod test {
     use super::*;
     use insta::{assert_debug_snapshot, assert_snapshot};
 
+    /// The world tests start from: default speed and colours, the first
+    /// snake heading `direction` and room for a second one, neither laid out
+    /// yet. Tests change whatever else they need on the result.
+    pub(crate) fn test_world(direction: Direction) -> World {
+        // A snake that starts still records it, see `queue_turn`.
+        let history = if direction == Direction::Still {
+            vec![Direction::Still]
+        } else {
+            Vec::new()
+        };
+        World::new(
+            direction,
+            history,
+            Vec::new(),
+            (0, 0),
+            false,
+            PauseState::Running,
+            100,
+            0,
+            0,
+            None,
+            Vec::new(),
+            TimeCycle::Forward,
+            Some(Vec::new()),
+            vec![Direction::Still],
+            Some(Vec::new()),
+            Direction::Still,
+            0,
+            0x0033CCFF,
+            0x0033CCCC,
+            0x00CC33FF,
+            0x00CC33CC,
+            0x0000FF00,
+            0x00FF0000,
+        )
+    }
+
     #[test]
     fn test_rgb() {
         assert_eq!(rgb(0, 0, 0), 0x00_00_00_00);
@@ -2199,8 +2597,7 @@ mod test {
             Vec::new(),
             (0, 0),
             false,
-            Instant::now(),
-            0,
+            PauseState::Running,
             100,
             0,
             0,
@@ -2363,8 +2760,7 @@ mod test {
             Vec::new(),
             (0, 0),
             false,
-            Instant::now(),
-            0,
+            PauseState::Running,
             100,
             0,
             0,
@@ -2456,8 +2852,7 @@ mod test {
             Vec::new(),
             (0, 0),
             false,
-            Instant::now(),
-            0,
+            PauseState::Running,
             100,
             0,
             0,
@@ -2550,8 +2945,7 @@ mod test {
             Vec::new(),
             (0, 0),
             false,
-            Instant::now(),
-            0,
+            PauseState::Running,
             100,
             0,
             0,
@@ -2674,8 +3068,7 @@ mod test {
             Vec::new(),
             (8, 1),
             false,
-            Instant::now(),
-            0,
+            PauseState::Running,
             100,
             0,
             0,
@@ -2908,8 +3301,7 @@ mod test {
             Vec::new(),
             (8, 1),
             false,
-            Instant::now(),
-            0,
+            PauseState::Running,
             100,
             0,
             0,
@@ -2996,4 +3388,813 @@ mod test {
             @r###""###
         );
     }
+
+    #[test]
+    fn quick_turns_are_buffered() {
+        let cli = Cli::parse();
+        let mut buffer: WindowBuffer = WindowBuffer::new(10, 10);
+        let mut game_elements = test_world(Direction::Still);
+        snake_generator(&mut game_elements, &buffer, &cli);
+
+        // Facing east before the first move, so going west is a reversal.
+        game_elements.turn_first_snake(Direction::West);
+        assert!(game_elements.first_snake_turns.is_empty());
+
+        // Setting off in that heading is fine though.
+        game_elements.turn_first_snake(Direction::East);
+        assert_eq!(
+            game_elements.first_snake_turns,
+            VecDeque::from(vec![Direction::East])
+        );
+        game_elements.first_snake_turns.clear();
+
+        // Two turns within a single tick: neither is lost, and the reversal
+        // queued right after them is rejected.
+        game_elements.turn_first_snake(Direction::North);
+        game_elements.turn_first_snake(Direction::West);
+        game_elements.turn_first_snake(Direction::East);
+        assert_eq!(
+            game_elements.first_snake_turns,
+            VecDeque::from(vec![Direction::North, Direction::West])
+        );
+
+        game_elements.update(&mut buffer, &cli);
+        assert_eq!(
+            game_elements.current_direction_first_snake,
+            Direction::North
+        );
+        game_elements.update(&mut buffer, &cli);
+        assert_eq!(game_elements.current_direction_first_snake, Direction::West);
+        game_elements.update(&mut buffer, &cli);
+        assert_eq!(game_elements.current_direction_first_snake, Direction::West);
+
+        assert_debug_snapshot!(
+            game_elements.snake,
+            @r###"
+        [
+            (
+                4,
+                4,
+            ),
+            (
+                3,
+                4,
+            ),
+            (
+                2,
+                4,
+            ),
+        ]
+        "###
+        );
+    }
+
+    #[test]
+    fn difficulty_profiles() {
+        let easy = Difficulty::Easy.profile();
+        let hard = Difficulty::Hard.profile();
+        assert!(easy.starting_speed > hard.starting_speed);
+        assert!(easy.food_score() < hard.food_score());
+
+        assert_eq!(hard.speed_after_food(100), 94);
+        assert_eq!(hard.speed_after_food(42), 40);
+        assert_eq!(hard.speed_after_food(3), 40);
+
+        assert!(easy.spawns_bad_berry(0));
+        assert!(!easy.spawns_bad_berry(1));
+        assert!(easy.spawns_bad_berry(4));
+
+        let mut custom = Difficulty::Medium.profile();
+        custom
+            .apply_overrides(
+                "# office league
+                name = office
+                starting_speed = 80
+
+                bad_berries_every = 0",
+            )
+            .unwrap();
+        assert_debug_snapshot!(custom, @r###"
+        DifficultyProfile {
+            name: "office",
+            starting_speed: 80,
+            speed_up_per_food: 4,
+            minimum_speed: 60,
+            bad_berries_every: 0,
+            score_multiplier: 1,
+        }
+        "###);
+        assert!(!custom.spawns_bad_berry(0));
+
+        assert!(custom.apply_overrides("speed = 3").is_err());
+        assert!(custom.apply_overrides("starting_speed fast").is_err());
+        assert!(custom.apply_overrides("minimum_speed = -1").is_err());
+    }
+
+    #[test]
+    fn game_loop_follows_the_world_clock() {
+        let cli = Cli::parse();
+        let clock = Arc::new(clock::ManualClock::new());
+        let mut buffer: WindowBuffer = WindowBuffer::new(10, 3);
+        let mut game_elements = test_world(Direction::East);
+        game_elements.clock = clock.clone();
+        snake_generator(&mut game_elements, &buffer, &cli);
+        let mut game_loop = game_loop::GameLoop::new(
+            1,
+            game_loop::DEFAULT_FRAME_INTERVAL,
+            game_elements.clock.now(),
+        );
+
+        let mut heads = Vec::new();
+        for _ in 0..25 {
+            clock.advance(Duration::from_millis(10));
+            game_loop.elapse(game_elements.clock.now());
+            while let Some(player) = game_loop.next_due(&game_elements.tick_intervals(&cli)) {
+                game_elements.tick(player, &mut buffer, &cli);
+            }
+            heads.push(game_elements.snake[game_elements.snake.len() - 1].0);
+        }
+
+        assert_eq!(
+            heads,
+            vec![4, 4, 4, 4, 4, 4, 4, 4, 4, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 6, 6, 6, 6, 6, 6]
+        );
+    }
+
+    #[test]
+    fn pause_and_resume_countdown() {
+        let cli = Cli::parse();
+        let clock = Arc::new(clock::ManualClock::new());
+        let mut buffer: WindowBuffer = WindowBuffer::new(30, 9);
+        let mut game_elements = test_world(Direction::East);
+        game_elements.clock = clock.clone();
+        snake_generator(&mut game_elements, &buffer, &cli);
+
+        game_elements.set_focus(false);
+        assert_eq!(game_elements.pause, PauseState::Paused);
+        assert_eq!(game_elements.tick_intervals(&cli), vec![None]);
+        display(&game_elements, &mut buffer, &cli);
+        assert_snapshot!(
+            buffer.to_string(),
+            @r###"
+        #.............................
+        ..............................
+        ...##...#..#.#..##.###.##.....
+        ...#.#.#.#.#.#.#...#...#.#....
+        ...##..###.####.#..##..#.#....
+        ...#...#.#.#.#...#.#...#.#....
+        ...#...#.#.###.##..###.##.....
+        ..............................
+        ..............................
+        "###
+        );
+
+        game_elements.toggle_pause();
+        clock.advance(Duration::from_millis(1500));
+        game_elements.update_pause();
+        display(&game_elements, &mut buffer, &cli);
+        assert_snapshot!(
+            buffer.to_string(),
+            @r###"
+        #.............................
+        ..............................
+        .............###..............
+        ...............#..............
+        ............####..............
+        .............#................
+        .............###..............
+        ..............................
+        ..............................
+        "###
+        );
+        assert_eq!(game_elements.tick_intervals(&cli), vec![None]);
+
+        clock.advance(Duration::from_millis(1500));
+        game_elements.update_pause();
+        assert_eq!(game_elements.pause, PauseState::Running);
+        assert_eq!(
+            game_elements.tick_intervals(&cli),
+            vec![Some(Duration::from_millis(100))]
+        );
+    }
+
+    #[test]
+    fn losing_focus_pauses_the_loop() {
+        let cli = Cli::parse_from(["snake"]);
+        let clock = Arc::new(clock::ManualClock::new());
+        let mut buffer: WindowBuffer = WindowBuffer::new(20, 5);
+        let mut game_elements = versus_world(Vec::new(), Vec::new());
+        game_elements.clock = clock.clone();
+        snake_generator(&mut game_elements, &buffer, &cli);
+        let mut game_loop =
+            game_loop::GameLoop::new(1, game_loop::DEFAULT_FRAME_INTERVAL, clock.now());
+        let mut frame = |game_elements: &mut World, focused: bool| {
+            clock.advance(Duration::from_millis(100));
+            game_elements.play_frame(focused, &mut game_loop, clock.now(), &mut buffer, &cli);
+            game_elements.snake[game_elements.snake.len() - 1]
+        };
+
+        let start = game_elements.snake[game_elements.snake.len() - 1];
+        let moved = frame(&mut game_elements, true);
+        assert_ne!(moved, start);
+        assert_eq!(frame(&mut game_elements, false), moved);
+        assert_eq!(game_elements.pause, PauseState::Paused);
+
+        // Coming back doesn't resume on its own.
+        assert_eq!(frame(&mut game_elements, true), moved);
+        game_elements.toggle_pause();
+        for _ in 0..RESUME_COUNTDOWN.as_millis() / 100 {
+            frame(&mut game_elements, true);
+            game_elements.update_pause();
+        }
+        assert_ne!(frame(&mut game_elements, true), moved);
+    }
+
+    #[test]
+    fn hud_shows_both_scores() {
+        let cli = Cli::parse_from(["snake", "--hud", "--two-players-mode"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(20, 16);
+        let mut game_elements = test_world(Direction::Still);
+        game_elements.food = (10, 10);
+        game_elements.score = 10;
+        game_elements.second_score = 7;
+        snake_generator(&mut game_elements, &buffer, &cli);
+
+        display(&game_elements, &mut buffer, &cli);
+        assert_snapshot!(
+            buffer.to_string(),
+            @r###"
+        ....................
+        ..#..###........###.
+        .##..#.#..........#.
+        ..#..#.#..........#.
+        ..#..#.#..........#.
+        .###.###..........#.
+        .......###..........
+        ....................
+        .......###..........
+        ....................
+        ..........#.........
+        ....................
+        ....................
+        ....................
+        ....................
+        ....................
+        "###
+        );
+    }
+
+    fn versus_world(snake: Vec<(usize, usize)>, second_snake: Vec<(usize, usize)>) -> World {
+        let mut world = test_world(Direction::East);
+        world.snake = snake;
+        world.second_snake = Some(second_snake);
+        world.second_snake_directions = vec![Direction::West];
+        world.current_direction_second_snake = Direction::West;
+        world
+    }
+
+    #[test]
+    fn versus_rules() {
+        let snake = [(2, 5), (3, 5), (4, 5)];
+
+        let head_on = versus_result(&snake, &[(6, 5), (5, 5), (4, 5)], None);
+        assert_snapshot!(head_on.unwrap(), @"Draw by head-on collision, lengths 3 and 3");
+        let head_on = versus_result(&snake, &[(7, 5), (6, 5), (5, 5), (4, 5)], None);
+        assert_snapshot!(head_on.unwrap(), @"Player 2 wins by head-on collision, lengths 3 and 4");
+        let into_body = versus_result(&snake, &[(3, 3), (3, 4), (3, 5)], None);
+        assert_snapshot!(into_body.unwrap(), @"Player 1 wins by crash into the other snake, lengths 3 and 3");
+        let into_itself = versus_result(&[(2, 5), (3, 5), (3, 6), (2, 6), (2, 5)], &snake, None);
+        assert_eq!(into_itself.unwrap().reason, EndReason::HitSelf);
+        let into_wall = versus_result(&snake, &[(3, 3), (3, 2), (3, 1)], Some(1));
+        assert_snapshot!(into_wall.unwrap(), @"Player 1 wins by crash into a wall, lengths 3 and 3");
+        assert_eq!(versus_result(&snake, &[(3, 3), (3, 2), (3, 1)], None), None);
+    }
+
+    #[test]
+    fn versus_ends_as_soon_as_snakes_meet() {
+        let cli = Cli::parse_from(["snake", "--two-players-mode"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(12, 10);
+
+        // Heads one cell apart: the first move already settles it, so the
+        // second snake doesn't get to move through the first one.
+        let mut game_elements = versus_world(
+            vec![(1, 5), (2, 5), (3, 5)],
+            vec![(7, 5), (6, 5), (5, 5), (4, 5)],
+        );
+        game_elements.update(&mut buffer, &cli);
+        assert!(game_elements.finished);
+        assert_snapshot!(game_elements.result.unwrap(), @"Player 2 wins by head-on collision, lengths 3 and 4");
+        assert_eq!(game_elements.second_snake.as_ref().unwrap()[3], (4, 5));
+
+        // Both moving into the same free cell on the same tick. The frame
+        // that settles it hands the result over, once.
+        let clock = Arc::new(clock::ManualClock::new());
+        let mut game_elements =
+            versus_world(vec![(1, 5), (2, 5), (3, 5)], vec![(7, 5), (6, 5), (5, 5)]);
+        game_elements.clock = clock.clone();
+        let mut game_loop =
+            game_loop::GameLoop::new(2, game_loop::DEFAULT_FRAME_INTERVAL, clock.now());
+        clock.advance(Duration::from_millis(100));
+        let result = game_elements.play_frame(true, &mut game_loop, clock.now(), &mut buffer, &cli);
+        assert_snapshot!(result.unwrap(), @"Draw by head-on collision, lengths 3 and 3");
+        clock.advance(Duration::from_millis(100));
+        let result = game_elements.play_frame(true, &mut game_loop, clock.now(), &mut buffer, &cli);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn handicaps_balance_two_players() {
+        assert_eq!(parse_handicap(""), Ok(Handicap::default()));
+        assert_debug_snapshot!(parse_handicap("speed=150, length=5,acceleration=50,lives=1"), @r###"
+        Ok(
+            Handicap {
+                speed: Some(
+                    150,
+                ),
+                length: Some(
+                    5,
+                ),
+                acceleration: 50,
+                extra_lives: 1,
+            },
+        )
+        "###);
+        assert!(parse_handicap("lives").is_err());
+        assert!(parse_handicap("armour=2").is_err());
+
+        let cli = Cli::parse_from([
+            "snake",
+            "--two-players-mode",
+            "--second-handicap",
+            "speed=150,length=5,acceleration=50,lives=1",
+        ]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(20, 10);
+        let mut game_elements = versus_world(Vec::new(), Vec::new());
+        snake_generator(&mut game_elements, &buffer, &cli);
+        assert_eq!(game_elements.snake.len(), 3);
+        assert_eq!(game_elements.second_snake.as_ref().unwrap().len(), 5);
+        assert_eq!(
+            game_elements.tick_intervals(&cli),
+            vec![
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(150))
+            ]
+        );
+        let difficulty = DifficultyProfile::default();
+        assert_eq!(cli.handicap(0).speed_after_food(&difficulty, 100), 96);
+        assert_eq!(cli.handicap(1).speed_after_food(&difficulty, 150), 148);
+
+        // The second snake runs into the first one, but has a life to spare.
+        game_elements.second_snake = Some(vec![(8, 8), (8, 7), (8, 6)]);
+        game_elements.current_direction_second_snake = Direction::North;
+        game_elements.update_second_snake(&mut buffer, &cli);
+        assert!(!game_elements.finished);
+        assert_eq!(game_elements.lives, [0, 0]);
+        assert_eq!(
+            game_elements.second_snake.as_ref().unwrap(),
+            &game_elements.spawns[1]
+        );
+
+        // Once the respawned snake has waited and its shield is gone, the
+        // next crash ends the game.
+        game_elements.respawn_wait = [0, 0];
+        game_elements.effects[1] = Effects::default();
+        game_elements.second_snake = Some(vec![(8, 8), (8, 7), (8, 6)]);
+        game_elements.current_direction_second_snake = Direction::North;
+        game_elements.update_second_snake(&mut buffer, &cli);
+        assert_snapshot!(game_elements.result.unwrap(), @"Player 1 wins by crash into the other snake, lengths 3 and 3");
+    }
+
+    #[test]
+    fn bad_berries_have_effects() {
+        let cli = Cli::parse_from(["snake"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(20, 10);
+        let mut game_elements = versus_world(vec![(1, 5), (2, 5), (3, 5), (4, 5)], Vec::new());
+        game_elements
+            .berries
+            .apply_overrides("[solo]\nblight = score_penalty:5 shrink:2 reverse:3 slow_down:3\n")
+            .unwrap();
+        game_elements.score = 30;
+        game_elements.food = (15, 2);
+        game_elements.bad_berries_position = Some((5, 5));
+        assert_eq!(game_elements.berry_colour(&cli), 0x00FF0000);
+
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, vec![(5, 5), (6, 5)]);
+        assert_eq!(game_elements.score, 25);
+        assert_eq!(
+            game_elements.tick_intervals(&cli),
+            vec![Some(Duration::from_millis(200))]
+        );
+
+        // Controls are mirrored while the effect lasts.
+        game_elements.first_snake_turns.push_back(Direction::North);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(
+            game_elements.current_direction_first_snake,
+            Direction::South
+        );
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.effects[0], Effects::default());
+        assert_eq!(
+            game_elements.tick_intervals(&cli),
+            vec![Some(Duration::from_millis(100))]
+        );
+    }
+
+    #[test]
+    fn several_food_items_at_once() {
+        let cli = Cli::parse_from(["snake", "--foods", "3"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(20, 10);
+        let mut game_elements = versus_world(vec![(1, 5), (2, 5), (3, 5)], Vec::new());
+        game_elements.rng = StdRng::seed_from_u64(1);
+        game_elements.food_generator(&buffer, &cli);
+        assert_eq!(game_elements.foods.len(), 2);
+
+        game_elements.food = (15, 2);
+        game_elements.foods[0] = FoodItem {
+            position: (4, 5),
+            multiplier: 3,
+            age: 0,
+            runaway: false,
+        };
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, vec![(1, 5), (2, 5), (3, 5), (4, 5)]);
+        assert_eq!(game_elements.score, 30);
+        assert_eq!(game_elements.foods.len(), 2);
+        assert!(!game_elements
+            .foods
+            .iter()
+            .any(|item| item.position == (4, 5)));
+
+        // Over time, items only show up every so many moves.
+        let cli = Cli::parse_from(["snake", "--foods", "2", "--food-spawn-ticks", "2"]);
+        let mut game_elements = versus_world(vec![(1, 5), (2, 5), (3, 5)], Vec::new());
+        game_elements.food_generator(&buffer, &cli);
+        game_elements.food = (15, 2);
+        assert!(game_elements.foods.is_empty());
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert!(game_elements.foods.is_empty());
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.foods.len(), 1);
+    }
+
+    #[test]
+    fn food_expires_and_runs_away() {
+        let cli = Cli::parse_from([
+            "snake",
+            "--foods",
+            "3",
+            "--food-lifetime",
+            "12",
+            "--food-vanishes",
+        ]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(20, 10);
+        let mut game_elements = versus_world(vec![(1, 5), (2, 5), (3, 5)], Vec::new());
+        game_elements.food = (15, 2);
+        game_elements.food_age = 11;
+        let runaway = FoodItem {
+            position: (6, 5),
+            multiplier: 1,
+            age: 2,
+            runaway: true,
+        };
+        let expiring = FoodItem {
+            position: (10, 8),
+            multiplier: 2,
+            age: 11,
+            runaway: false,
+        };
+        game_elements.foods = vec![runaway, expiring];
+
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_ne!(game_elements.food, (15, 2));
+        assert_eq!(game_elements.food_age, 0);
+        assert_eq!(
+            game_elements.foods,
+            vec![FoodItem {
+                position: (6, 6),
+                age: 3,
+                ..runaway
+            }]
+        );
+    }
+
+    #[test]
+    fn portals_carry_the_snake_through() {
+        let cli = Cli::parse_from(["snake", "--portal", "4,5:10,2"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(14, 7);
+        let mut game_elements = versus_world(vec![(1, 5), (2, 5), (3, 5)], Vec::new());
+        game_elements.food = (12, 2);
+        game_elements.rng = StdRng::seed_from_u64(1);
+        game_elements.portal_generator(&buffer, &cli);
+        assert_eq!(game_elements.portals, cli.portals);
+
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, vec![(2, 5), (3, 5), (10, 2)]);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, vec![(3, 5), (10, 2), (11, 2)]);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.score, 10);
+        assert!(!game_elements.is_portal(game_elements.food));
+
+        display(&game_elements, &mut buffer, &cli);
+        assert_snapshot!(
+            buffer.to_string(),
+            @r###"
+        ..............
+        ..............
+        ..........####
+        ..............
+        .............#
+        ....#.........
+        ..............
+        "###
+        );
+    }
+
+    #[test]
+    fn topologies_join_the_edges() {
+        let cli = Cli::parse_from(["snake", "--topology", "mobius"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(10, 6);
+        let mut game_elements = versus_world(vec![(7, 1), (8, 1), (9, 1)], Vec::new());
+        game_elements.food = (5, 5);
+
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, vec![(8, 1), (9, 1), (0, 4)]);
+
+        // The top and bottom edges of a Möbius strip are walls.
+        game_elements.first_snake_turns.push_back(Direction::South);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, vec![(9, 1), (0, 4), (0, 5)]);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert!(game_elements.finished);
+
+        // Ghost mode still wraps everywhere.
+        let cli = Cli::parse_from(["snake", "--topology", "mobius", "--ghost-mode"]);
+        let mut game_elements = versus_world(vec![(7, 1), (8, 1), (9, 1)], Vec::new());
+        game_elements.food = (5, 5);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, vec![(8, 1), (9, 1), (0, 1)]);
+    }
+
+    #[test]
+    fn biting_cuts_instead_of_killing() {
+        let cli = Cli::parse_from(["snake", "--tail-biting"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(20, 10);
+        let mut game_elements = versus_world(
+            vec![(3, 4), (3, 5), (4, 5), (5, 5), (5, 4), (4, 4)],
+            Vec::new(),
+        );
+        game_elements.food = (15, 1);
+        game_elements.score = 50;
+        game_elements.current_direction_first_snake = Direction::South;
+        game_elements.first_snake_directions = vec![Direction::West];
+
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, vec![(5, 5), (5, 4), (4, 4), (4, 5)]);
+        assert_eq!(game_elements.score, 30);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert!(!game_elements.finished);
+
+        // In versus mode, the other snake's tail changes hands.
+        let cli = Cli::parse_from(["snake", "--tail-biting", "--two-players-mode"]);
+        let mut game_elements = versus_world(
+            vec![(1, 5), (2, 5), (3, 5)],
+            vec![(4, 2), (4, 3), (4, 4), (4, 5), (4, 6)],
+        );
+        game_elements.food = (15, 1);
+        game_elements.second_score = 100;
+
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.result, None);
+        assert_eq!(game_elements.second_snake, Some(vec![(4, 6)]));
+        assert_eq!(game_elements.snake.len(), 7);
+        assert_eq!(game_elements.snake[6], (4, 5));
+        assert_eq!((game_elements.score, game_elements.second_score), (40, 60));
+    }
+
+    #[test]
+    fn lives_respawn_snakes_shielded() {
+        let cli = Cli::parse_from(["snake", "--lives", "2", "--hud"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(30, 16);
+        let mut game_elements = versus_world(Vec::new(), Vec::new());
+        game_elements.food = (25, 1);
+        snake_generator(&mut game_elements, &buffer, &cli);
+        assert_eq!(game_elements.lives, [2, 2]);
+
+        // Crashing into a wall costs a life instead of the game.
+        game_elements.snake = vec![(27, 3), (28, 3), (29, 3)];
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert!(!game_elements.finished);
+        assert_eq!(game_elements.lives[0], 1);
+        assert_eq!(game_elements.snake, game_elements.spawns[0]);
+        assert_eq!(game_elements.respawn_wait[0], RESPAWN_DELAY);
+
+        display(&game_elements, &mut buffer, &cli);
+        assert_snapshot!(
+            buffer.to_string(),
+            @r###"
+        ..............................
+        .###.....................#....
+        .#.#..........................
+        .#.#..........................
+        .#.#..........................
+        .###..........................
+        ..............................
+        .#............................
+        ............###...............
+        ..............................
+        ..##..#..###.###.....###.###..
+        .#...#.#.#...#.........#.#.#..
+        ..#..###.##..##......###.#.#..
+        ...#.#.#.#...#.......#...#.#..
+        .##..#.#.#...###.....###.###..
+        ..............................
+        "###
+        );
+
+        // It waits, then sets off shielded.
+        for _ in 0..RESPAWN_DELAY {
+            game_elements.update_first_snake(&mut buffer, &cli);
+        }
+        assert_eq!(game_elements.snake, game_elements.spawns[0]);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake[2], (15, 8));
+        assert!(game_elements.effects[0].has(Effect::Shielded));
+
+        // Shielded, it goes through its own body.
+        game_elements.snake = vec![(3, 4), (3, 5), (4, 5), (5, 5), (5, 4), (4, 4)];
+        game_elements.current_direction_first_snake = Direction::South;
+        game_elements.update_first_snake(&mut buffer, &cli);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert!(!game_elements.finished);
+        assert_eq!(game_elements.lives[0], 1);
+    }
+
+    #[test]
+    fn respawned_snakes_turn_from_their_spawn_heading() {
+        let cli = Cli::parse_from(["snake", "--lives", "1"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(30, 16);
+        let mut game_elements = versus_world(Vec::new(), Vec::new());
+        game_elements.food = (25, 1);
+        snake_generator(&mut game_elements, &buffer, &cli);
+
+        // Heading west into the wall, the snake comes back facing east.
+        game_elements.snake = vec![(2, 3), (1, 3), (0, 3)];
+        game_elements.current_direction_first_snake = Direction::West;
+        game_elements.first_snake_directions = vec![Direction::West];
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, game_elements.spawns[0]);
+
+        // Turning right isn't taken for turning back on the old heading.
+        game_elements.turn_first_snake(Direction::East);
+        assert_eq!(
+            game_elements.first_snake_turns,
+            VecDeque::from(vec![Direction::East])
+        );
+        for _ in 0..=RESPAWN_DELAY {
+            game_elements.update_first_snake(&mut buffer, &cli);
+        }
+        let spawn_head = game_elements.spawns[0][2];
+        assert_eq!(game_elements.snake[2], (spawn_head.0 + 1, spawn_head.1));
+        assert_eq!(
+            game_elements.first_snake_directions.last(),
+            Some(&Direction::East)
+        );
+    }
+
+    #[test]
+    fn light_cycles_leave_trails() {
+        let cli = Cli::parse_from(["snake", "--tron", "--two-players-mode"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(20, 10);
+        let mut game_elements = versus_world(Vec::new(), Vec::new());
+        snake_generator(&mut game_elements, &buffer, &cli);
+        assert_eq!(
+            game_elements.tick_intervals(&cli),
+            vec![Some(Duration::from_millis(TRON_SPEED as u64)); 2]
+        );
+
+        // Nothing to eat, the trail just gets longer.
+        game_elements.snake = vec![(1, 5), (2, 5), (3, 5)];
+        game_elements.second_snake = Some(vec![(8, 5), (7, 5), (6, 5), (5, 5)]);
+        game_elements.food = (4, 5);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.snake, vec![(1, 5), (2, 5), (3, 5), (4, 5)]);
+        assert_eq!(game_elements.score, 1);
+
+        // Head-on, the longer trail doesn't help.
+        game_elements.update_second_snake(&mut buffer, &cli);
+        assert_snapshot!(game_elements.result.unwrap(), @"Draw by head-on collision, lengths 4 and 5");
+    }
+
+    #[test]
+    fn arena_shrinks_around_the_snakes() {
+        let cli = Cli::parse_from(["snake", "--two-players-mode", "--shrink-every", "1"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(20, 10);
+        let mut game_elements = versus_world(
+            vec![(2, 0), (3, 0), (4, 0)],
+            vec![(15, 5), (14, 5), (13, 5)],
+        );
+        game_elements.rng = StdRng::seed_from_u64(5);
+
+        // The ring about to close in flashes.
+        for _ in 0..9 {
+            game_elements.update(&mut buffer, &cli);
+        }
+        assert_eq!(game_elements.border, 0);
+        display(&game_elements, &mut buffer, &cli);
+        assert_snapshot!(
+            buffer.to_string(),
+            @r###"
+        ####################
+        #..................#
+        #..................#
+        #..................#
+        #..................#
+        #...###............#
+        #..................#
+        #..................#
+        #..................#
+        ####################
+        "###
+        );
+
+        // Once it has, the snake caught in it is gone and the food moves in.
+        game_elements.update(&mut buffer, &cli);
+        assert_eq!(game_elements.border, 1);
+        assert!(game_elements.in_arena(game_elements.food, &buffer));
+        assert_snapshot!(game_elements.result.unwrap(), @"Player 2 wins by crash into a wall, lengths 3 and 3");
+
+        // The new edges are walls like any other.
+        assert_eq!(
+            game_elements.step((18, 5), &Direction::East, &buffer, &cli),
+            None
+        );
+        assert_eq!(
+            game_elements.step((1, 5), &Direction::East, &buffer, &cli),
+            Some((2, 5))
+        );
+    }
+
+    #[test]
+    fn power_ups_run_out_and_rewind() {
+        let cli = Cli::parse_from(["snake", "--power-ups", "--hud"]);
+        let mut buffer: WindowBuffer = WindowBuffer::new(24, 12);
+        let mut game_elements = versus_world(vec![(1, 5), (2, 5), (3, 5)], Vec::new());
+        game_elements.food = (20, 1);
+        game_elements.power_up = Some(PowerUp {
+            position: (4, 5),
+            kind: power_ups::PowerUpKind::ScoreMultiplier,
+        });
+        game_elements.foods.push(FoodItem {
+            position: (5, 5),
+            multiplier: 1,
+            age: 0,
+            runaway: false,
+        });
+
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.power_up, None);
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert_eq!(game_elements.score, 20);
+
+        display(&game_elements, &mut buffer, &cli);
+        assert_snapshot!(
+            buffer.to_string(),
+            @r###"
+        ........................
+        .###.###............#...
+        ...#.#.#................
+        .###.#.#................
+        .#...#.#................
+        .#######................
+        .#.#.###.....#.#.###....
+        .#.#...#.....#.#.#.#....
+        ..#..###.....###.###....
+        .#.#.#.........#.#.#....
+        .#.#.###.......#.###....
+        ........................
+        "###
+        );
+
+        // Going back a move gives the power-up back and takes its effect away.
+        return_in_time(&mut game_elements, &cli);
+        return_in_time(&mut game_elements, &cli);
+        assert_eq!(game_elements.effects[0], Effects::default());
+        assert_eq!(game_elements.power_up.unwrap().position, (4, 5));
+
+        // Invincible snakes cut through their own body.
+        let mut game_elements = versus_world(
+            vec![(3, 4), (3, 5), (4, 5), (5, 5), (5, 4), (4, 4)],
+            Vec::new(),
+        );
+        game_elements.food = (20, 1);
+        game_elements.current_direction_first_snake = Direction::South;
+        game_elements.first_snake_directions = vec![Direction::West];
+        game_elements.effects[0].start(&power_ups::PowerUpKind::Invincibility.effect());
+        game_elements.update_first_snake(&mut buffer, &cli);
+        assert!(!game_elements.finished);
+        assert_eq!(game_elements.snake, vec![(5, 5), (5, 4), (4, 4), (4, 5)]);
+    }
 }
@@ -0,0 +1,401 @@
+//! Lockstep network play. Both computers run the same seeded `World` and only
+//! exchange the turns taken each tick, along with a hash of their state so a
+//! game that drifted apart is noticed instead of silently diverging. A tick
+//! moves one snake: each keeps its own pace on a game clock both sides
+//! derive from the world, never from their own clocks.
+
+use crate::connection::Connection;
+use crate::effects::BerryConfig;
+use crate::food::FoodSettings;
+use crate::game_loop::Schedule;
+use crate::portals::Portal;
+use crate::topology::Topology;
+use crate::{
+    snake_generator, Cli, DifficultyProfile, Dimensions, Direction, Handicap, World, TRON_SPEED,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use web_time::Duration;
+
+/// Everything both sides need to agree on before the first tick.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Settings {
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    pub snake_size_start: usize,
+    pub bad_berries: bool,
+    pub ghost_mode: bool,
+    pub topology: Topology,
+    pub tail_biting: bool,
+    pub lives: usize,
+    pub tron: bool,
+    /// Starting tick interval in milliseconds before handicaps, tron's
+    /// unless a speed was given.
+    pub snake_speed: usize,
+    pub shrink_every: Option<u64>,
+    pub difficulty: DifficultyProfile,
+    pub handicaps: [Handicap; 2],
+    pub berries: BerryConfig,
+    pub foods: FoodSettings,
+    pub power_ups: bool,
+    pub portals: Vec<Portal>,
+    pub random_portals: usize,
+}
+
+impl Settings {
+    pub fn from_cli(
+        cli: &Cli,
+        difficulty: DifficultyProfile,
+        berries: BerryConfig,
+        seed: u64,
+    ) -> Self {
+        Self {
+            seed,
+            width: cli.width,
+            height: cli.height,
+            snake_size_start: cli.snake_size_start,
+            bad_berries: cli.bad_berries,
+            ghost_mode: cli.ghost_mode,
+            topology: cli.topology,
+            tail_biting: cli.tail_biting,
+            lives: cli.lives,
+            tron: cli.tron,
+            snake_speed: match cli.snake_speed {
+                None if cli.tron => TRON_SPEED,
+                _ => difficulty.starting_speed,
+            },
+            shrink_every: cli.shrink_every,
+            difficulty,
+            handicaps: [cli.handicap(0), cli.handicap(1)],
+            berries,
+            foods: FoodSettings::from_cli(cli),
+            power_ups: cli.power_ups,
+            portals: cli.portals.clone(),
+            random_portals: cli.random_portals,
+        }
+    }
+
+    /// Makes `cli` match the host's game.
+    pub fn apply_to_cli(&self, cli: &mut Cli) {
+        cli.width = self.width;
+        cli.height = self.height;
+        cli.snake_size_start = self.snake_size_start;
+        cli.bad_berries = self.bad_berries;
+        cli.ghost_mode = self.ghost_mode;
+        cli.topology = self.topology;
+        cli.tail_biting = self.tail_biting;
+        cli.lives = self.lives;
+        cli.tron = self.tron;
+        cli.snake_speed = Some(self.snake_speed);
+        cli.shrink_every = self.shrink_every;
+        cli.first_handicap = Some(self.handicaps[0].clone());
+        cli.second_handicap = Some(self.handicaps[1].clone());
+        cli.foods = self.foods.count;
+        cli.food_spawn_ticks = self.foods.spawn_ticks;
+        cli.food_lifetime = self.foods.lifetime;
+        cli.food_vanishes = self.foods.vanishes;
+        cli.runaway_food = self.foods.runaway;
+        cli.power_ups = self.power_ups;
+        cli.portals = self.portals.clone();
+        cli.random_portals = self.random_portals;
+        cli.two_players_mode = true;
+    }
+
+    /// Sets up the board the same way on both sides.
+    pub fn prepare(&self, world: &mut World, buffer: &impl Dimensions, cli: &Cli) {
+        world.rng = StdRng::seed_from_u64(self.seed);
+        world.difficulty = self.difficulty.clone();
+        world.berries = self.berries.clone();
+        world.food_generator(buffer, cli);
+        snake_generator(world, buffer, cli);
+        // After `snake_generator`, which only goes by the local command line.
+        world.snake_speed = self.handicaps[0].speed.unwrap_or(self.snake_speed);
+        world.second_snake_speed = self.handicaps[1].speed.unwrap_or(self.snake_speed);
+        world.portal_generator(buffer, cli);
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum LockstepMessage {
+    Hello(Box<Settings>),
+    /// The turns a player took for `tick`, and the hash of their world
+    /// right before running it.
+    Input {
+        tick: u64,
+        turns: Vec<Direction>,
+        hash: u64,
+    },
+}
+
+/// Both sides had different worlds going into `tick`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Desync {
+    pub tick: u64,
+    pub local: u64,
+    pub remote: u64,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "games out of sync at tick {}: local state {:016x}, remote state {:016x}",
+            self.tick, self.local, self.remote
+        )
+    }
+}
+
+pub struct Lockstep {
+    connection: Connection<LockstepMessage>,
+    /// 0 for the host, who plays the first snake, 1 for the other side.
+    pub player: usize,
+    pub tick: u64,
+    pending: Vec<Direction>,
+    schedule: Schedule,
+}
+
+impl Lockstep {
+    /// Waits for the other player and sends them the game settings.
+    pub fn accept(listener: &TcpListener, settings: &Settings) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        let mut connection = Connection::new(stream)?;
+        connection.send(&LockstepMessage::Hello(Box::new(settings.clone())))?;
+
+        Ok(Self::new(connection, 0))
+    }
+
+    /// Connects to a host and returns the settings of its game.
+    pub fn join<A: ToSocketAddrs>(addr: A) -> io::Result<(Self, Settings)> {
+        let mut connection = Connection::new(TcpStream::connect(addr)?)?;
+
+        let LockstepMessage::Hello(settings) = connection.receive()? else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected the game settings from the host",
+            ));
+        };
+
+        Ok((Self::new(connection, 1), *settings))
+    }
+
+    fn new(connection: Connection<LockstepMessage>, player: usize) -> Self {
+        Self {
+            connection,
+            player,
+            tick: 0,
+            pending: Vec::new(),
+            schedule: Schedule::default(),
+        }
+    }
+
+    /// Records a turn from the local player, sent with the next tick.
+    pub fn queue_turn(&mut self, direction: Direction) {
+        self.pending.push(direction);
+    }
+
+    /// Game time of the next tick since the game started, `None` while
+    /// neither snake is moving.
+    pub fn next_tick(&mut self, world: &World, cli: &Cli) -> Option<Duration> {
+        self.schedule
+            .next(&world.tick_intervals(cli))
+            .map(|(_, at)| at)
+    }
+
+    /// Runs one tick: swaps inputs with the other side, waiting for theirs,
+    /// then applies both players' turns in the same order and moves the
+    /// snake that is due. A desync found on the way is returned, the tick
+    /// still runs. Does nothing while neither snake is moving.
+    pub fn step(
+        &mut self,
+        world: &mut World,
+        buffer: &impl Dimensions,
+        cli: &Cli,
+    ) -> io::Result<Option<Desync>> {
+        let Some((moving, at)) = self.schedule.next(&world.tick_intervals(cli)) else {
+            return Ok(None);
+        };
+
+        let local_turns = std::mem::take(&mut self.pending);
+        let local_hash = world.state_hash();
+        self.connection.send(&LockstepMessage::Input {
+            tick: self.tick,
+            turns: local_turns.clone(),
+            hash: local_hash,
+        })?;
+
+        let (remote_turns, remote_hash) = match self.connection.receive()? {
+            LockstepMessage::Input { tick, turns, hash } if tick == self.tick => (turns, hash),
+            message => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected input for tick {}, got {:?}", self.tick, message),
+                ))
+            }
+        };
+
+        let desync = (local_hash != remote_hash).then_some(Desync {
+            tick: self.tick,
+            local: local_hash,
+            remote: remote_hash,
+        });
+
+        let (first_turns, second_turns) = if self.player == 0 {
+            (local_turns, remote_turns)
+        } else {
+            (remote_turns, local_turns)
+        };
+        for turn in first_turns {
+            world.turn_first_snake(turn);
+        }
+        for turn in second_turns {
+            world.turn_second_snake(turn);
+        }
+        if moving == 0 {
+            world.update_first_snake(buffer, cli);
+        } else {
+            world.update_second_snake(buffer, cli);
+        }
+
+        self.schedule.moved(moving, at);
+        self.tick += 1;
+        Ok(desync)
+    }
+}
+
+#[cfg(all(test, feature = "gui"))]
+mod test {
+    use super::*;
+    use crate::test::test_world;
+    use clap::Parser;
+    use std::thread;
+    use window_rs::WindowBuffer;
+
+    /// How a game went on one side.
+    struct Played {
+        /// Hash of the final world.
+        hash: u64,
+        desyncs: Vec<Desync>,
+        /// Game time of every tick.
+        times: Vec<Duration>,
+    }
+
+    /// Plays `ticks` ticks on one side, turning as scripted. `cli` is that
+    /// side's own command line.
+    fn play(
+        mut lockstep: Lockstep,
+        settings: Settings,
+        mut cli: Cli,
+        script: Vec<(u64, Direction)>,
+        ticks: u64,
+        tamper_at: Option<u64>,
+    ) -> Played {
+        settings.apply_to_cli(&mut cli);
+        let mut buffer = WindowBuffer::new(settings.width, settings.height);
+        let mut world = test_world(Direction::Still);
+        settings.prepare(&mut world, &buffer, &cli);
+
+        let mut desyncs = Vec::new();
+        let mut times = Vec::new();
+        for tick in 0..ticks {
+            for (_, direction) in script.iter().filter(|(at, _)| *at == tick) {
+                lockstep.queue_turn(direction.clone());
+            }
+            if tamper_at == Some(tick) {
+                world.score += 1;
+            }
+            times.extend(lockstep.next_tick(&world, &cli));
+            desyncs.extend(lockstep.step(&mut world, &mut buffer, &cli).unwrap());
+        }
+        Played {
+            hash: world.state_hash(),
+            desyncs,
+            times,
+        }
+    }
+
+    fn run(args: &[&str], tamper_at: Option<u64>) -> (Played, Played) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cli = Cli::parse_from(
+            ["snake", "--width", "20", "--height", "12", "--bad-berries"]
+                .iter()
+                .chain(args),
+        );
+        let settings = Settings::from_cli(
+            &cli,
+            DifficultyProfile::from_cli(&cli).unwrap(),
+            BerryConfig::default(),
+            42,
+        );
+
+        let joining = thread::spawn(move || {
+            let (lockstep, settings) = Lockstep::join(addr).unwrap();
+            play(
+                lockstep,
+                settings,
+                Cli::parse_from(["snake"]),
+                vec![
+                    (2, Direction::North),
+                    (6, Direction::East),
+                    (9, Direction::South),
+                ],
+                20,
+                None,
+            )
+        });
+        let lockstep = Lockstep::accept(&listener, &settings).unwrap();
+        let hosting = play(
+            lockstep,
+            settings,
+            cli,
+            vec![
+                (1, Direction::South),
+                (4, Direction::West),
+                (5, Direction::North),
+            ],
+            20,
+            tamper_at,
+        );
+
+        (hosting, joining.join().unwrap())
+    }
+
+    #[test]
+    fn both_sides_stay_in_step() {
+        for args in [&[][..], &["--tron", "--snake-speed", "90"]] {
+            let (host, join) = run(args, None);
+            assert_eq!(host.hash, join.hash);
+            assert_eq!(host.desyncs, Vec::new());
+            assert_eq!(join.desyncs, Vec::new());
+        }
+    }
+
+    #[test]
+    fn snakes_keep_their_own_pace() {
+        let (host, join) = run(
+            &[
+                "--first-handicap",
+                "speed=100,acceleration=0",
+                "--second-handicap",
+                "speed=150,acceleration=0",
+            ],
+            None,
+        );
+        assert_eq!(host.hash, join.hash);
+        assert_eq!(host.times, join.times);
+        let expected = [100, 150, 200, 300, 300, 400, 450].map(Duration::from_millis);
+        assert_eq!(host.times[..7], expected);
+    }
+
+    #[test]
+    fn desyncs_are_reported_on_both_sides() {
+        let (host, join) = run(&[], Some(10));
+        assert_eq!(host.desyncs[0].tick, 10);
+        assert_eq!(join.desyncs[0].tick, 10);
+        assert_eq!(host.desyncs[0].local, join.desyncs[0].remote);
+    }
+}
@@ -1,14 +1,369 @@
 use clap::Parser;
 use graphic::{minifb::Minifb, Graphic};
 use snake::{
-    display, go_display, return_in_time, snake_generator, Cli, Direction, TimeCycle, World,
+    arena::{Arena, ArenaSettings},
+    bot::{self, BotMatch, OnFailure},
+    clock::{Clock, SystemClock},
+    connection, display,
+    effects::BerryConfig,
+    game_loop::{GameLoop, DEFAULT_FRAME_INTERVAL, MAX_CATCH_UP_TICKS},
+    go_display,
+    lockstep::{Lockstep, Settings},
+    net::{Client, ClientMessage, Host, View},
+    series::{self, Series},
+    server::{self, Request, Response},
+    snake_generator,
+    tournament::{self, TournamentSettings},
+    Cli, Command, DifficultyProfile, Direction, World,
 };
-use std::{fs::File, io::Read};
+use std::{fs::File, io::Read, net::TcpListener, sync::Arc};
 use web_time::{Duration, Instant};
 use window_rs::WindowBuffer;
 
+const ARROW_KEYS: [(graphic::Key, Direction); 4] = [
+    (graphic::Key::Up, Direction::North),
+    (graphic::Key::Down, Direction::South),
+    (graphic::Key::Left, Direction::West),
+    (graphic::Key::Right, Direction::East),
+];
+
+/// Plays a game joined over the network: our turns go to the host and the
+/// board drawn is whatever the host sent last. Spectators only get to watch.
+fn join(cli: &Cli, mut client: Client) -> std::io::Result<()> {
+    let mut buffer: WindowBuffer = WindowBuffer::new(client.width, client.height);
+    let title = match client.player {
+        Some(_) => "Snake - ESC to exit",
+        None => "Snake - spectating - ESC to exit",
+    };
+    let mut window = Minifb::new(title, buffer.width(), buffer.height());
+
+    let clock = Arc::new(SystemClock);
+    let mut game_elements = World::starting(DifficultyProfile::from_cli(cli)?, clock);
+    // The board is drawn the host's way, only the HUD is up to us.
+    let mut cli = cli.clone();
+    client.view.apply_to(&mut cli, &mut game_elements);
+    let cli = &cli;
+    client.wait_snapshot()?.apply_to(&mut game_elements);
+
+    while window.is_open() && !window.is_key_down(graphic::Key::Escape) {
+        if client.player.is_some() {
+            for (key, direction) in ARROW_KEYS.iter() {
+                if window.is_key_pressed(*key) {
+                    client.send(ClientMessage::Turn(direction.clone()))?;
+                }
+            }
+            if window.get_keys_released().contains(&graphic::Key::Space) {
+                client.send(ClientMessage::TogglePause)?;
+            }
+        }
+
+        match client.latest_snapshot() {
+            Ok(Some(snapshot)) => snapshot.apply_to(&mut game_elements),
+            Ok(None) => (),
+            Err(_) => {
+                println!("The host left the game");
+                break;
+            }
+        }
+
+        if !game_elements.finished {
+            display(&game_elements, &mut buffer, cli);
+        } else {
+            go_display(&mut game_elements, &mut buffer, cli);
+        }
+        window.update_with_buffer(&buffer);
+
+        std::thread::sleep(DEFAULT_FRAME_INTERVAL);
+    }
+
+    Ok(())
+}
+
+/// Plays in a room of a snake server, or lists its rooms when none is given.
+/// Without a name, the room is only watched.
+fn connect(addr: &str, room: Option<String>, name: Option<&str>) -> std::io::Result<()> {
+    let mut client = server::Client::connect(connection::with_default_port(addr))?;
+
+    let Some(room) = room else {
+        if let Response::Rooms(rooms) = client.request(&Request::ListRooms)? {
+            for room in rooms {
+                println!(
+                    "{}: {}/{} players{}",
+                    room.name,
+                    room.seated.len(),
+                    room.players,
+                    if room.playing { ", playing" } else { "" }
+                );
+            }
+        }
+        return Ok(());
+    };
+
+    let request = match name {
+        Some(name) => Request::JoinRoom {
+            room,
+            name: name.to_string(),
+        },
+        None => Request::Spectate { room },
+    };
+    let room = match client.request(&request)? {
+        Response::Joined { room, player } => {
+            println!(
+                "Seated as player {}, waiting for the room to fill up",
+                player + 1
+            );
+            room
+        }
+        Response::Spectating { room } => room,
+        _ => return Err(std::io::Error::other("unexpected answer from the server")),
+    };
+
+    let mut buffer: WindowBuffer = WindowBuffer::new(room.arena.width, room.arena.height);
+    let mut window = Minifb::new("Snake - ESC to exit", buffer.width(), buffer.height());
+    // The server's world is drawn through a local one, like a network game.
+    let mut cli = Cli::parse_from(["snake"]);
+    cli.two_players_mode = room.players == 2;
+    let mut game_elements = World::starting(room.arena.difficulty.clone(), Arc::new(SystemClock));
+
+    while window.is_open() && !window.is_key_down(graphic::Key::Escape) {
+        if name.is_some() {
+            for (key, direction) in ARROW_KEYS.iter() {
+                if window.is_key_pressed(*key) {
+                    client.send(&Request::Turn(direction.clone()))?;
+                }
+            }
+        }
+
+        while let Some(response) = client.try_receive()? {
+            match response {
+                Response::State { board, .. } => {
+                    board.apply_to(&mut game_elements);
+                    display(&game_elements, &mut buffer, &cli);
+                }
+                Response::MatchOver(record) => {
+                    println!("{}", record.result);
+                    if name.is_some() {
+                        return Ok(());
+                    }
+                }
+                _ => (),
+            }
+        }
+        window.update_with_buffer(&buffer);
+
+        std::thread::sleep(DEFAULT_FRAME_INTERVAL);
+    }
+
+    Ok(())
+}
+
+/// Runs a match between bots, in a window unless `headless`.
+fn play_bots(
+    cli: &Cli,
+    commands: &[String],
+    budget: Duration,
+    on_failure: OnFailure,
+    max_ticks: u64,
+    headless: bool,
+) -> std::io::Result<()> {
+    let bots = commands
+        .iter()
+        .map(|command| bot::connect(command, budget))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let settings = ArenaSettings::from_cli(cli, DifficultyProfile::from_cli(cli)?);
+    let arena = Arena::new(settings, commands.to_vec(), rand::random())?;
+    let mut game = BotMatch::new(arena, bots, on_failure);
+
+    if !headless {
+        let mut buffer: WindowBuffer = WindowBuffer::new(cli.width, cli.height);
+        let mut window = Minifb::new("Snake - ESC to exit", buffer.width(), buffer.height());
+        let started = Instant::now();
+
+        while window.is_open()
+            && !window.is_key_down(graphic::Key::Escape)
+            && !game.finished()
+            && game.arena.tick < max_ticks
+        {
+            // Wait for the move's game time, so the snakes go at their pace.
+            if let Some((_, at)) = game.arena.next_move() {
+                std::thread::sleep((started + at).saturating_duration_since(Instant::now()));
+            }
+            game.step();
+            display(&game.arena.world, &mut buffer, game.arena.cli());
+            window.update_with_buffer(&buffer);
+        }
+        println!("{}", game.end());
+    } else {
+        println!("{}", game.play(max_ticks));
+    }
+
+    Ok(())
+}
+
+/// Plays a lockstep game: both sides simulate the world, only turns are sent.
+fn play_lockstep(cli: &Cli, mut session: Lockstep, settings: &Settings) -> std::io::Result<()> {
+    let mut buffer: WindowBuffer = WindowBuffer::new(cli.width, cli.height);
+    let mut window = Minifb::new("Snake - ESC to exit", buffer.width(), buffer.height());
+
+    let clock = Arc::new(SystemClock);
+    let mut game_elements = World::starting(settings.difficulty.clone(), clock.clone());
+    settings.prepare(&mut game_elements, &buffer, cli);
+
+    // Ticks run on the game clock both sides share: `start` maps it to ours.
+    let mut start = clock.now();
+    let mut game_loop = GameLoop::new(0, DEFAULT_FRAME_INTERVAL, start);
+
+    'game: while window.is_open() && !window.is_key_down(graphic::Key::Escape) {
+        for (key, direction) in ARROW_KEYS.iter() {
+            if window.is_key_pressed(*key) {
+                session.queue_turn(direction.clone());
+            }
+        }
+
+        let now = clock.now();
+        let settled = game_elements.result.is_some();
+        let mut caught_up = 0;
+        while let Some(at) = session.next_tick(&game_elements, cli) {
+            if start + at > now {
+                break;
+            }
+            // After a stall, drop the time lost instead of racing through it.
+            if caught_up == 2 * MAX_CATCH_UP_TICKS {
+                start = now - at;
+            }
+            if let Some(desync) = session.step(&mut game_elements, &mut buffer, cli)? {
+                println!("{}", desync);
+                break 'game;
+            }
+            caught_up += 1;
+        }
+        if let (false, Some(result)) = (settled, game_elements.result) {
+            println!("{result}");
+        }
+
+        if game_loop.should_render(now) {
+            if !game_elements.finished {
+                display(&game_elements, &mut buffer, cli);
+            } else {
+                go_display(&mut game_elements, &mut buffer, cli);
+            }
+            window.update_with_buffer(&buffer);
+        }
+
+        let until_tick = session
+            .next_tick(&game_elements, cli)
+            .map_or(Duration::MAX, |at| {
+                (start + at).saturating_duration_since(now)
+            });
+        std::thread::sleep(game_loop.time_until_next(now, &[]).min(until_tick));
+    }
+
+    Ok(())
+}
+
 fn main() -> std::io::Result<()> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+
+    let mut host = None;
+    match cli.command.clone() {
+        Some(Command::Join {
+            addr,
+            lockstep: true,
+        }) => {
+            let (session, settings) = Lockstep::join(connection::with_default_port(&addr))?;
+            settings.apply_to_cli(&mut cli);
+            return play_lockstep(&cli, session, &settings);
+        }
+        Some(Command::Join { addr, .. }) => {
+            cli.two_players_mode = true;
+            return join(&cli, Client::connect(connection::with_default_port(&addr))?);
+        }
+        Some(Command::Watch {
+            addr,
+            room: Some(room),
+        }) => return connect(&addr, Some(room), None),
+        Some(Command::Watch { addr, room: None }) => {
+            cli.two_players_mode = true;
+            cli.hud = true;
+            return join(
+                &cli,
+                Client::spectate(connection::with_default_port(&addr))?,
+            );
+        }
+        Some(Command::Host {
+            addr,
+            lockstep: true,
+        }) => {
+            let listener = TcpListener::bind(connection::with_default_port(&addr))?;
+            println!("Waiting for a player on {}", listener.local_addr()?);
+            let settings = Settings::from_cli(
+                &cli,
+                DifficultyProfile::from_cli(&cli)?,
+                BerryConfig::from_cli(&cli)?,
+                rand::random(),
+            );
+            let session = Lockstep::accept(&listener, &settings)?;
+            settings.apply_to_cli(&mut cli);
+            return play_lockstep(&cli, session, &settings);
+        }
+        Some(Command::Host { addr, .. }) => {
+            cli.two_players_mode = true;
+            let mut listening = Host::bind(connection::with_default_port(&addr))?;
+            println!("Waiting for a player on {}", listening.local_addr()?);
+            let view = View::from_cli(&cli, BerryConfig::from_cli(&cli)?);
+            let player = listening.accept(cli.width, cli.height, view)?;
+            println!("{} joined the game", player);
+            host = Some(listening);
+        }
+        Some(Command::Bots {
+            bots,
+            budget,
+            on_failure,
+            max_ticks,
+            headless,
+        }) => {
+            let budget = Duration::from_millis(budget);
+            return play_bots(&cli, &bots, budget, on_failure, max_ticks, headless);
+        }
+        Some(Command::Tournament {
+            bots,
+            seeds,
+            sizes,
+            threads,
+            budget,
+            on_failure,
+            max_ticks,
+            csv,
+            json,
+        }) => {
+            let settings = TournamentSettings {
+                bots,
+                seeds: (0..seeds).collect(),
+                sizes: if sizes.is_empty() {
+                    vec![(cli.width, cli.height)]
+                } else {
+                    sizes
+                },
+                arena: ArenaSettings::from_cli(&cli, DifficultyProfile::from_cli(&cli)?),
+                budget: Duration::from_millis(budget),
+                on_failure,
+                max_ticks,
+                threads,
+            };
+            let report = tournament::run(&settings)?;
+            println!("{}", report);
+
+            if let Some(csv) = csv {
+                std::fs::write(csv, report.to_csv())?;
+            }
+            if let Some(json) = json {
+                std::fs::write(json, serde_json::to_string_pretty(&report)?)?;
+            }
+            return Ok(());
+        }
+        Some(Command::Connect { addr, room, name }) => return connect(&addr, room, Some(&name)),
+        None => (),
+    }
 
     let mut buffer: WindowBuffer = WindowBuffer::new(cli.width, cli.height);
 
@@ -49,64 +404,83 @@ fn main() -> std::io::Result<()> {
         }*/
     }
 
+    let difficulty = DifficultyProfile::from_cli(&cli)?;
+    let berries = BerryConfig::from_cli(&cli)?;
+    let clock = Arc::new(SystemClock);
+
     let mut window = Minifb::new("Snake - ESC to exit", buffer.width(), buffer.height());
 
-    let mut game_elements: World = World::new(
-        Direction::Still,
-        vec![Direction::Still],
-        Vec::new(),
-        (0, 0),
-        false,
-        Instant::now(),
-        0,
-        cli.snake_speed,
-        0,
-        0,
-        None,
-        Vec::new(),
-        TimeCycle::Forward,
-        Some(Vec::new()),
-        vec![Direction::Still],
-        Some(Vec::new()),
-        Direction::Still,
-        0,
-        0xFF33CCFF,
-        0xFFCC66FF,
-        0xFFCC33FF,
-        0xFFFFCC00,
-        0xFF00FF00,
-        0xFFFF0000,
-    );
+    let mut game_elements = World::starting(difficulty.clone(), clock.clone());
+    game_elements.berries = berries.clone();
     game_elements.food_generator(&buffer, &cli);
     snake_generator(&mut game_elements, &buffer, &cli);
+    game_elements.portal_generator(&buffer, &cli);
 
-    let mut instant = Instant::now();
+    let mut series = cli
+        .best_of
+        .filter(|_| cli.two_players_mode)
+        .map(Series::new);
+
+    let players = if cli.two_players_mode { 2 } else { 1 };
+    let mut game_loop = GameLoop::new(players, DEFAULT_FRAME_INTERVAL, clock.now());
 
     while window.is_open() && !window.is_key_down(graphic::Key::Escape) {
         let _ = game_elements.handle_user_input(&window, &cli, &buffer);
-        if game_elements.time_cycle == TimeCycle::Forward {
-            if game_elements.finished == false {
-                let elapsed_time = Duration::from_millis(game_elements.snake_speed as u64);
+        if let Some(host) = host.as_mut() {
+            host.accept_spectators(&game_elements);
+            if host.apply_inputs(&mut game_elements).is_err() {
+                println!("The other player left the game");
+                break;
+            }
+        }
+
+        let now = clock.now();
+        if let Some(result) =
+            game_elements.play_frame(window.is_active(), &mut game_loop, now, &mut buffer, &cli)
+        {
+            println!("{result}");
+        }
 
-                if instant.elapsed() >= elapsed_time {
-                    game_elements.update(&mut buffer, &cli);
-                    instant = Instant::now();
+        if let Some(series) = series.as_mut() {
+            if let Some(result) = game_elements.result {
+                if series.intermission.is_none() && !series.is_over() {
+                    series.record(&result, now);
+                    println!("{}", series);
                 }
+            }
+            if series.next_round(now) {
+                game_elements = World::starting(difficulty.clone(), clock.clone());
+                game_elements.berries = berries.clone();
+                game_elements.food_generator(&buffer, &cli);
+                snake_generator(&mut game_elements, &buffer, &cli);
+                game_elements.portal_generator(&buffer, &cli);
+                if series.sides_swapped() {
+                    series::swap_spawn_sides(&mut game_elements);
+                }
+                game_loop = GameLoop::new(players, DEFAULT_FRAME_INTERVAL, now);
+            }
+        }
+
+        if game_loop.should_render(now) {
+            if game_elements.finished == false {
                 display(&game_elements, &mut buffer, &cli);
             } else {
                 go_display(&mut game_elements, &mut buffer, &cli);
+                if let Some(series) = &series {
+                    series::display_series(series, &game_elements, &mut buffer);
+                }
             }
-        } else if game_elements.time_cycle == TimeCycle::Backward {
-            let elapsed_time = Duration::from_millis(100);
+            window.update_with_buffer(&buffer);
+        }
 
-            if instant.elapsed() >= elapsed_time {
-                return_in_time(&mut game_elements, &cli);
-                instant = Instant::now();
+        if let Some(host) = host.as_mut() {
+            if host.broadcast(&game_elements).is_err() {
+                println!("The other player left the game");
+                break;
             }
-            display(&game_elements, &mut buffer, &cli);
-            game_elements.time_cycle = TimeCycle::Pause;
         }
-        window.update_with_buffer(&buffer)
+
+        std::thread::sleep(game_loop.time_until_next(now, &game_elements.tick_intervals(&cli)));
     }
 
     Ok(())
@@ -0,0 +1,537 @@
+//! Local network play. The host runs the only real `World`, clients send it
+//! their turns and get a `Snapshot` of the board back every time it changes.
+//! Spectators get the same snapshots but have no say in the game.
+//! Messages are JSON, one per line, see `connection`.
+
+use crate::connection::Connection;
+use crate::effects::{BerryConfig, Effects};
+use crate::food::{FoodItem, FoodSettings};
+use crate::portals::Portal;
+use crate::power_ups::PowerUp;
+use crate::{Cli, Direction, PauseState, VersusResult, World};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use web_time::{Duration, Instant};
+
+/// Everything a client needs to draw the board.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Snapshot {
+    pub snake: Vec<(usize, usize)>,
+    pub second_snake: Option<Vec<(usize, usize)>>,
+    pub food: (usize, usize),
+    pub food_age: u64,
+    pub foods: Vec<FoodItem>,
+    pub power_up: Option<PowerUp>,
+    pub portals: Vec<Portal>,
+    pub border: usize,
+    pub shrink_elapsed: Duration,
+    pub effects: [Effects; 2],
+    pub bad_berries_position: Option<(usize, usize)>,
+    pub berry_kind: usize,
+    pub score: usize,
+    pub second_score: usize,
+    pub lives: [usize; 2],
+    pub respawn_wait: [u64; 2],
+    pub finished: bool,
+    pub result: Option<VersusResult>,
+    pub paused: bool,
+    /// Whole seconds left on the resume countdown, if one is running.
+    pub resuming_in: Option<u64>,
+}
+
+impl Snapshot {
+    pub fn from_world(world: &World) -> Self {
+        let (paused, resuming_in) = match world.pause {
+            PauseState::Running => (false, None),
+            PauseState::Paused => (true, None),
+            PauseState::Resuming(until) => {
+                let left = until.saturating_duration_since(world.clock.now());
+                (true, Some(left.as_millis().div_ceil(1000) as u64))
+            }
+        };
+
+        Self {
+            snake: world.snake.clone(),
+            second_snake: world.second_snake.clone(),
+            food: world.food,
+            food_age: world.food_age,
+            foods: world.foods.clone(),
+            power_up: world.power_up,
+            portals: world.portals.clone(),
+            border: world.border,
+            shrink_elapsed: world.shrink_elapsed,
+            effects: world.effects.clone(),
+            bad_berries_position: world.bad_berries_position,
+            berry_kind: world.berry_kind,
+            score: world.score,
+            second_score: world.second_score,
+            lives: world.lives,
+            respawn_wait: world.respawn_wait,
+            finished: world.finished,
+            result: world.result,
+            paused,
+            resuming_in,
+        }
+    }
+
+    /// Copies the board into `world` so it can go through the usual `display`.
+    pub fn apply_to(&self, world: &mut World) {
+        world.snake = self.snake.clone();
+        world.second_snake = self.second_snake.clone();
+        world.food = self.food;
+        world.food_age = self.food_age;
+        world.foods = self.foods.clone();
+        world.power_up = self.power_up;
+        world.portals = self.portals.clone();
+        world.border = self.border;
+        world.shrink_elapsed = self.shrink_elapsed;
+        world.effects = self.effects.clone();
+        world.bad_berries_position = self.bad_berries_position;
+        world.berry_kind = self.berry_kind;
+        world.score = self.score;
+        world.second_score = self.second_score;
+        world.lives = self.lives;
+        world.respawn_wait = self.respawn_wait;
+        world.finished = self.finished;
+        world.result = self.result;
+        world.pause = match (self.paused, self.resuming_in) {
+            (false, _) => PauseState::Running,
+            (true, None) => PauseState::Paused,
+            (true, Some(seconds)) => {
+                PauseState::Resuming(world.clock.now() + Duration::from_secs(seconds))
+            }
+        };
+    }
+}
+
+/// The host's settings that change how the board is drawn, so those joining
+/// or watching see the host's game rather than one drawn by their own
+/// settings.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct View {
+    pub tron: bool,
+    pub shrink_every: Option<u64>,
+    pub foods: FoodSettings,
+    pub berries: BerryConfig,
+}
+
+impl View {
+    pub fn from_cli(cli: &Cli, berries: BerryConfig) -> Self {
+        Self {
+            tron: cli.tron,
+            shrink_every: cli.shrink_every,
+            foods: FoodSettings::from_cli(cli),
+            berries,
+        }
+    }
+
+    /// Makes `cli` and `world` draw the board the way the host does.
+    pub fn apply_to(&self, cli: &mut Cli, world: &mut World) {
+        cli.two_players_mode = true;
+        cli.tron = self.tron;
+        cli.shrink_every = self.shrink_every;
+        cli.foods = self.foods.count;
+        cli.food_spawn_ticks = self.foods.spawn_ticks;
+        cli.food_lifetime = self.foods.lifetime;
+        cli.food_vanishes = self.foods.vanishes;
+        cli.runaway_food = self.foods.runaway;
+        world.berries = self.berries.clone();
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum ClientMessage {
+    /// First message on a connection, to play the second snake.
+    Join,
+    /// First message on a connection, to only watch.
+    Spectate,
+    Turn(Direction),
+    TogglePause,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum ServerMessage {
+    /// First message on a connection: board size, how to draw it and which
+    /// snake is yours.
+    Welcome {
+        width: usize,
+        height: usize,
+        view: View,
+        player: usize,
+    },
+    /// First message to a spectator, followed right away by the current board.
+    Spectating {
+        width: usize,
+        height: usize,
+        view: View,
+    },
+    State(Box<Snapshot>),
+}
+
+/// How long a new connection has to say whether it plays or watches.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Hosting side of a networked game. The host plays the first snake and the
+/// client that joins plays the second one. Any number of spectators can
+/// come and go, before or during the game.
+pub struct Host {
+    listener: TcpListener,
+    clients: Vec<Connection<ClientMessage>>,
+    spectators: Vec<Connection<ClientMessage>>,
+    /// Connections that haven't said yet whether they play or watch, and
+    /// since when.
+    pending: Vec<(Connection<ClientMessage>, Instant)>,
+    width: usize,
+    height: usize,
+    view: Option<View>,
+    last_sent: Option<Snapshot>,
+}
+
+impl Host {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            clients: Vec::new(),
+            spectators: Vec::new(),
+            pending: Vec::new(),
+            width: 0,
+            height: 0,
+            view: None,
+            last_sent: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Blocks until a player joins and greets them. Spectators turning up
+    /// in the meantime are let in too, and connections that stay silent are
+    /// dropped after `HANDSHAKE_TIMEOUT` without holding anyone else up.
+    pub fn accept(&mut self, width: usize, height: usize, view: View) -> io::Result<SocketAddr> {
+        self.width = width;
+        self.height = height;
+        self.view = Some(view.clone());
+        self.listener.set_nonblocking(true)?;
+
+        loop {
+            self.take_newcomers()?;
+            while let Some((mut client, hello)) = self.next_hello() {
+                match hello {
+                    ClientMessage::Join => {
+                        client.send(&ServerMessage::Welcome {
+                            width,
+                            height,
+                            view,
+                            player: 1,
+                        })?;
+                        let addr = client.peer_addr()?;
+                        self.clients.push(client);
+                        return Ok(addr);
+                    }
+                    ClientMessage::Spectate => self.add_spectator(client, None),
+                    _ => (),
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Lets in the spectators who connected since the last call, and catches
+    /// them up with the current board. Anyone else trying to join is turned
+    /// away as the game is already full. Spectators are extras: if the
+    /// listener breaks, no new ones get in but the game goes on.
+    pub fn accept_spectators(&mut self, world: &World) {
+        let _ = self.take_newcomers();
+        while let Some((spectator, hello)) = self.next_hello() {
+            if hello == ClientMessage::Spectate {
+                self.add_spectator(spectator, Some(Snapshot::from_world(world)));
+            }
+        }
+    }
+
+    /// Moves the connections waiting on the listener to `pending`. Newcomers
+    /// that can't be set up are dropped, so this only fails along with the
+    /// listener.
+    fn take_newcomers(&mut self) -> io::Result<()> {
+        while let Some(connection) = Connection::accept(&self.listener)? {
+            self.pending.push((connection, Instant::now()));
+        }
+        Ok(())
+    }
+
+    /// The next pending connection that said hello, along with what it said.
+    /// Those that left or stayed silent too long are dropped on the way.
+    fn next_hello(&mut self) -> Option<(Connection<ClientMessage>, ClientMessage)> {
+        let mut index = 0;
+        while index < self.pending.len() {
+            let (connection, since) = &mut self.pending[index];
+            match connection.try_receive() {
+                Ok(Some(hello)) => return Some((self.pending.remove(index).0, hello)),
+                Ok(None) if since.elapsed() < HANDSHAKE_TIMEOUT => index += 1,
+                _ => {
+                    self.pending.remove(index);
+                }
+            }
+        }
+        None
+    }
+
+    fn add_spectator(&mut self, mut spectator: Connection<ClientMessage>, board: Option<Snapshot>) {
+        let Some(view) = self.view.clone() else {
+            return;
+        };
+        let greeting = ServerMessage::Spectating {
+            width: self.width,
+            height: self.height,
+            view,
+        };
+        let sent = spectator.send(&greeting).and_then(|()| match board {
+            Some(snapshot) => spectator.send(&ServerMessage::State(Box::new(snapshot))),
+            None => Ok(()),
+        });
+        if sent.is_ok() {
+            self.spectators.push(spectator);
+        }
+    }
+
+    pub fn spectators(&self) -> usize {
+        self.spectators.len()
+    }
+
+    /// Applies everything the client sent since the last call.
+    pub fn apply_inputs(&mut self, world: &mut World) -> io::Result<()> {
+        for client in self.clients.iter_mut() {
+            while let Some(message) = client.try_receive()? {
+                match message {
+                    ClientMessage::Turn(direction) => world.turn_second_snake(direction),
+                    ClientMessage::TogglePause => world.toggle_pause(),
+                    ClientMessage::Join | ClientMessage::Spectate => (),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the board to every client, unless it hasn't changed since last
+    /// time. Only losing the player is an error, spectators who left are
+    /// simply forgotten.
+    pub fn broadcast(&mut self, world: &World) -> io::Result<()> {
+        let snapshot = Snapshot::from_world(world);
+        if self.last_sent.as_ref() == Some(&snapshot) {
+            return Ok(());
+        }
+
+        let message = ServerMessage::State(Box::new(snapshot.clone()));
+        for client in self.clients.iter_mut() {
+            client.send(&message)?;
+        }
+        self.spectators
+            .retain_mut(|spectator| spectator.send(&message).is_ok());
+        self.last_sent = Some(snapshot);
+        Ok(())
+    }
+}
+
+/// Joining side of a networked game.
+pub struct Client {
+    connection: Connection<ServerMessage>,
+    pub width: usize,
+    pub height: usize,
+    pub view: View,
+    /// The snake we play, `None` for a spectator.
+    pub player: Option<usize>,
+}
+
+impl Client {
+    /// Connects and waits for the host's welcome.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::open(addr, ClientMessage::Join)
+    }
+
+    /// Connects as a spectator.
+    pub fn spectate<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::open(addr, ClientMessage::Spectate)
+    }
+
+    fn open<A: ToSocketAddrs>(addr: A, hello: ClientMessage) -> io::Result<Self> {
+        let mut connection = Connection::new(TcpStream::connect(addr)?)?;
+        connection.send(&hello)?;
+
+        let (width, height, view, player) = match connection.receive()? {
+            ServerMessage::Welcome {
+                width,
+                height,
+                view,
+                player,
+            } => (width, height, view, Some(player)),
+            ServerMessage::Spectating {
+                width,
+                height,
+                view,
+            } => (width, height, view, None),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected a welcome from the host",
+                ))
+            }
+        };
+
+        Ok(Self {
+            connection,
+            width,
+            height,
+            view,
+            player,
+        })
+    }
+
+    pub fn send(&mut self, message: ClientMessage) -> io::Result<()> {
+        self.connection.send(&message)
+    }
+
+    /// The most recent board received, skipping any older ones still queued.
+    pub fn latest_snapshot(&mut self) -> io::Result<Option<Snapshot>> {
+        let mut latest = None;
+        while let Some(message) = self.connection.try_receive()? {
+            if let ServerMessage::State(snapshot) = message {
+                latest = Some(*snapshot);
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Waits for the next board from the host.
+    pub fn wait_snapshot(&mut self) -> io::Result<Snapshot> {
+        loop {
+            if let ServerMessage::State(snapshot) = self.connection.receive()? {
+                return Ok(*snapshot);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "gui"))]
+mod test {
+    use super::*;
+    use crate::test::test_world;
+    use crate::{snake_generator, Cli};
+    use clap::Parser;
+    use window_rs::WindowBuffer;
+
+    fn two_player_world() -> World {
+        let mut world = test_world(Direction::Still);
+        world.food = (1, 1);
+        let cli = Cli::parse_from(["snake", "--two-players-mode"]);
+        snake_generator(&mut world, &WindowBuffer::new(10, 6), &cli);
+        world
+    }
+
+    fn tron_view() -> View {
+        let cli = Cli::parse_from(["snake", "--tron", "--shrink-every", "20"]);
+        View::from_cli(&cli, BerryConfig::default())
+    }
+
+    #[test]
+    fn host_and_client_over_localhost() {
+        let mut host = Host::bind("127.0.0.1:0").unwrap();
+        let addr = host.local_addr().unwrap();
+        let joining = thread::spawn(move || Client::connect(addr).unwrap());
+        host.accept(10, 6, tron_view()).unwrap();
+        let mut client = joining.join().unwrap();
+        assert_eq!(
+            (client.width, client.height, client.player),
+            (10, 6, Some(1))
+        );
+        let mut cli = Cli::parse_from(["snake"]);
+        let mut remote = two_player_world();
+        client.view.apply_to(&mut cli, &mut remote);
+        assert!(cli.two_players_mode && cli.tron);
+        assert_eq!(cli.shrink_every, Some(20));
+
+        let mut world = two_player_world();
+        client.send(ClientMessage::Turn(Direction::North)).unwrap();
+        client.send(ClientMessage::TogglePause).unwrap();
+        while world.second_snake_turns.is_empty() || world.pause == PauseState::Running {
+            host.apply_inputs(&mut world).unwrap();
+        }
+        assert_eq!(
+            world.second_snake_turns,
+            std::collections::VecDeque::from(vec![Direction::North])
+        );
+
+        host.broadcast(&world).unwrap();
+        let snapshot = client.wait_snapshot().unwrap();
+        assert_eq!(snapshot, Snapshot::from_world(&world));
+        assert!(snapshot.paused);
+
+        let mut remote = two_player_world();
+        remote.snake.clear();
+        snapshot.apply_to(&mut remote);
+        assert_eq!(remote.snake, world.snake);
+        assert_eq!(remote.second_snake, world.second_snake);
+        assert_eq!(remote.pause, PauseState::Paused);
+
+        // Nothing changed, so nothing new is sent.
+        host.broadcast(&world).unwrap();
+        assert_eq!(client.latest_snapshot().unwrap(), None);
+
+        drop(host);
+        while client.latest_snapshot().is_ok() {}
+    }
+
+    #[test]
+    fn spectators_catch_up_and_have_no_say() {
+        let mut host = Host::bind("127.0.0.1:0").unwrap();
+        let addr = host.local_addr().unwrap();
+        let early = thread::spawn(move || Client::spectate(addr).unwrap());
+        let joining = thread::spawn(move || Client::connect(addr).unwrap());
+        host.accept(10, 6, tron_view()).unwrap();
+        let _player = joining.join().unwrap();
+
+        let mut world = two_player_world();
+        world.score = 30;
+        // The early spectator may still be waiting behind the player.
+        while !early.is_finished() {
+            host.accept_spectators(&world);
+        }
+        let mut early = early.join().unwrap();
+        assert_eq!(early.player, None);
+        assert_eq!(early.view, tron_view());
+        host.broadcast(&world).unwrap();
+        assert_eq!(early.wait_snapshot().unwrap().score, 30);
+
+        world.second_score = 20;
+        let late = thread::spawn(move || {
+            let mut late = Client::spectate(addr).unwrap();
+            let snapshot = late.wait_snapshot().unwrap();
+            (late, snapshot)
+        });
+        while !late.is_finished() {
+            host.accept_spectators(&world);
+        }
+        let (mut late, snapshot) = late.join().unwrap();
+        assert_eq!(snapshot, Snapshot::from_world(&world));
+        assert_eq!(host.spectators(), 2);
+
+        late.send(ClientMessage::Turn(Direction::North)).unwrap();
+        host.accept_spectators(&world);
+        host.apply_inputs(&mut world).unwrap();
+        assert!(world.second_snake_turns.is_empty());
+    }
+
+    #[test]
+    fn silent_connections_dont_hold_up_the_player() {
+        let mut host = Host::bind("127.0.0.1:0").unwrap();
+        let addr = host.local_addr().unwrap();
+        let _silent = TcpStream::connect(addr).unwrap();
+        let joining = thread::spawn(move || Client::connect(addr).unwrap());
+
+        host.accept(10, 6, tron_view()).unwrap();
+        assert_eq!(joining.join().unwrap().player, Some(1));
+        assert_eq!(host.pending.len(), 1);
+    }
+}
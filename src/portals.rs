@@ -0,0 +1,88 @@
+//! Portals come in pairs of cells: a head moving into one comes out of the
+//! other, keeping its heading, and the body follows it through. Pairs are
+//! given with `--portal` or put on random free cells with `--random-portals`.
+use crate::rgb;
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Portal {
+    pub ends: [(usize, usize); 2],
+}
+
+impl Portal {
+    /// Whether both ends fit on a `width` by `height` board.
+    pub fn fits(&self, width: usize, height: usize) -> bool {
+        self.ends.iter().all(|(x, y)| *x < width && *y < height)
+    }
+
+    /// Colour of the pair at `index`, a few of them taking turns.
+    pub fn colour(index: usize) -> u32 {
+        match index % 3 {
+            0 => rgb(0x80, 0, u8::MAX),
+            1 => rgb(u8::MAX, 0x80, 0xC0),
+            _ => rgb(0, 0xC0, 0x80),
+        }
+    }
+}
+
+/// Where a head moving into `cell` comes out, if `cell` is a portal.
+pub fn exit(portals: &[Portal], cell: (usize, usize)) -> Option<(usize, usize)> {
+    portals.iter().find_map(|portal| match portal.ends {
+        [from, to] if from == cell => Some(to),
+        [to, from] if from == cell => Some(to),
+        _ => None,
+    })
+}
+
+/// Parses a pair of portal cells written like `3,4:20,10`.
+pub fn parse_portal(text: &str) -> Result<Portal, String> {
+    let cell = |end: &str| -> Result<(usize, usize), String> {
+        let (x, y) = end
+            .split_once(',')
+            .ok_or_else(|| format!("missing `,` in portal: {text}"))?;
+        let number = |n: &str| {
+            n.trim()
+                .parse()
+                .map_err(|_| format!("invalid number in portal: {text}"))
+        };
+        Ok((number(x)?, number(y)?))
+    };
+    let (from, to) = text
+        .split_once(':')
+        .ok_or_else(|| format!("missing `:` in portal: {text}"))?;
+
+    let ends = [cell(from)?, cell(to)?];
+    if ends[0] == ends[1] {
+        return Err(format!("both ends of the portal are the same cell: {text}"));
+    }
+    Ok(Portal { ends })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn portals_go_both_ways() {
+        let portals = [
+            parse_portal("3,4:20,10").unwrap(),
+            parse_portal(" 0,0 : 1,1 ").unwrap(),
+        ];
+        assert_eq!(exit(&portals, (3, 4)), Some((20, 10)));
+        assert_eq!(exit(&portals, (20, 10)), Some((3, 4)));
+        assert_eq!(exit(&portals, (1, 1)), Some((0, 0)));
+        assert_eq!(exit(&portals, (4, 3)), None);
+        assert!(portals[0].fits(21, 11));
+        assert!(!portals[0].fits(20, 11));
+
+        assert_eq!(
+            parse_portal("3,4"),
+            Err("missing `:` in portal: 3,4".to_string())
+        );
+        assert_eq!(
+            parse_portal("3,4:5"),
+            Err("missing `,` in portal: 3,4:5".to_string())
+        );
+        assert!(parse_portal("3,4:3,4").is_err());
+    }
+}
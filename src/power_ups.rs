@@ -0,0 +1,110 @@
+//! Power-ups turn up now and then with the food when `--power-ups` is on.
+//! Each one starts an effect on the snake picking it up, most of them for a
+//! number of its moves, which the HUD counts down.
+use crate::effects::{Effect, EffectSpec, Stacking};
+use crate::rgb;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// One food out of this many comes with a power-up, when none is out yet.
+pub const POWER_UP_CHANCE: u32 = 4;
+
+/// How far away, in moves, the magnet reaches for food.
+pub const MAGNET_RANGE: usize = 6;
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum PowerUpKind {
+    Invincibility,
+    SpeedBoost,
+    ScoreMultiplier,
+    ShrinkTail,
+    Magnet,
+    Phase,
+}
+
+impl PowerUpKind {
+    pub const ALL: [PowerUpKind; 6] = [
+        PowerUpKind::Invincibility,
+        PowerUpKind::SpeedBoost,
+        PowerUpKind::ScoreMultiplier,
+        PowerUpKind::ShrinkTail,
+        PowerUpKind::Magnet,
+        PowerUpKind::Phase,
+    ];
+
+    pub fn effect(&self) -> EffectSpec {
+        let (effect, moves) = match self {
+            PowerUpKind::Invincibility => (Effect::Invincible, 30),
+            PowerUpKind::SpeedBoost => (Effect::SpeedUp, 30),
+            PowerUpKind::ScoreMultiplier => (Effect::ScoreMultiplier(2), 50),
+            PowerUpKind::ShrinkTail => (Effect::Shrink(3), 0),
+            PowerUpKind::Magnet => (Effect::Magnet, 50),
+            PowerUpKind::Phase => (Effect::Ghost, 50),
+        };
+        EffectSpec {
+            effect,
+            moves,
+            stacking: Stacking::Extend,
+        }
+    }
+
+    pub fn colour(&self) -> u32 {
+        match self {
+            PowerUpKind::Invincibility => rgb(u8::MAX, u8::MAX, u8::MAX),
+            PowerUpKind::SpeedBoost => rgb(u8::MAX, 0, u8::MAX),
+            PowerUpKind::ScoreMultiplier => rgb(u8::MAX, 0xD7, 0),
+            PowerUpKind::ShrinkTail => rgb(0x80, 0x80, u8::MAX),
+            PowerUpKind::Magnet => rgb(0xC0, 0xC0, 0xC0),
+            PowerUpKind::Phase => rgb(0, 0x80, u8::MAX),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PowerUp {
+    pub position: (usize, usize),
+    pub kind: PowerUpKind,
+}
+
+impl PowerUp {
+    pub fn random(position: (usize, usize), rng: &mut impl Rng) -> Self {
+        let kind = PowerUpKind::ALL[rng.gen_range(0..PowerUpKind::ALL.len())];
+        Self { position, kind }
+    }
+}
+
+/// Where a magnet pulls food at `position` towards `head`: one cell closer
+/// along the longest way, as long as it's in range and doesn't land on the
+/// head itself.
+pub fn pull(position: (usize, usize), head: (usize, usize)) -> Option<(usize, usize)> {
+    let (dx, dy) = (position.0.abs_diff(head.0), position.1.abs_diff(head.1));
+    if dx + dy <= 1 || dx + dy > MAGNET_RANGE {
+        return None;
+    }
+    let step = |from: usize, to: usize| if from < to { from + 1 } else { from - 1 };
+
+    if dx >= dy {
+        Some((step(position.0, head.0), position.1))
+    } else {
+        Some((position.0, step(position.1, head.1)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn magnets_only_reach_so_far() {
+        assert_eq!(pull((10, 5), (5, 5)), Some((9, 5)));
+        assert_eq!(pull((5, 2), (6, 5)), Some((5, 3)));
+        assert_eq!(pull((6, 5), (5, 5)), None);
+        assert_eq!(pull((12, 5), (5, 5)), None);
+
+        assert_eq!(PowerUpKind::ShrinkTail.effect().moves, 0);
+        assert!(PowerUpKind::ALL
+            .iter()
+            .filter(|kind| **kind != PowerUpKind::ShrinkTail)
+            .all(|kind| kind.effect().effect.is_timed()));
+    }
+}
@@ -0,0 +1,229 @@
+//! Best-of-N series of two players rounds. The series keeps the score across
+//! rounds, shows an intermission screen between them and swaps the spawn
+//! sides every other round.
+use crate::{rgb, text, VersusResult, World};
+use std::fmt;
+use web_time::{Duration, Instant};
+use window_rs::WindowBuffer;
+
+/// How long the score is shown between two rounds.
+pub const INTERMISSION: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub best_of: usize,
+    pub wins: [usize; 2],
+    /// Drawn rounds don't count towards the series, they are played again.
+    pub draws: usize,
+    /// Round being played, from 1.
+    pub round: usize,
+    /// When the intermission before the next round ends, while there is one.
+    pub intermission: Option<Instant>,
+}
+
+impl Series {
+    pub fn new(best_of: usize) -> Self {
+        Self {
+            best_of: best_of.max(1),
+            wins: [0, 0],
+            draws: 0,
+            round: 1,
+            intermission: None,
+        }
+    }
+
+    /// Rounds a player has to win to take the series.
+    pub fn wins_needed(&self) -> usize {
+        self.best_of / 2 + 1
+    }
+
+    pub fn winner(&self) -> Option<usize> {
+        (0..2).find(|player| self.wins[*player] >= self.wins_needed())
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.winner().is_some()
+    }
+
+    /// Whether the players start this round on each other's side.
+    pub fn sides_swapped(&self) -> bool {
+        self.round % 2 == 0
+    }
+
+    /// Counts a finished round and starts the intermission, unless that was
+    /// the last one.
+    pub fn record(&mut self, result: &VersusResult, now: Instant) {
+        match result.winner {
+            Some(player) => self.wins[player] += 1,
+            None => self.draws += 1,
+        }
+        if !self.is_over() {
+            self.intermission = Some(now + INTERMISSION);
+        }
+    }
+
+    /// Moves on to the next round once the intermission is over. Returns
+    /// whether it did, meaning the board has to be set up again.
+    pub fn next_round(&mut self, now: Instant) -> bool {
+        match self.intermission {
+            Some(until) if now >= until => {
+                self.intermission = None;
+                self.round += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Series {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.winner() {
+            Some(player) => write!(f, "Player {} wins the series", player + 1)?,
+            None => write!(f, "Round {} of best of {}", self.round, self.best_of)?,
+        }
+        write!(f, " {}-{}", self.wins[0], self.wins[1])?;
+        if self.draws > 0 {
+            write!(f, " ({} drawn)", self.draws)?;
+        }
+        Ok(())
+    }
+}
+
+/// Puts each snake on the row the other one spawned on, keeping their
+/// headings, so nobody always gets the same side of the board.
+pub fn swap_spawn_sides(world: &mut World) {
+    let Some(second_snake) = world.second_snake.as_mut() else {
+        return;
+    };
+    let first_row = world.snake[world.snake.len() - 1].1;
+    let second_row = second_snake[second_snake.len() - 1].1;
+
+    world.snake.iter_mut().for_each(|(_, y)| *y = second_row);
+    second_snake.iter_mut().for_each(|(_, y)| *y = first_row);
+    world.spawns = [world.snake.clone(), second_snake.clone()];
+}
+
+/// Shows the series score over the dimmed board, each player in the colour
+/// of their snake, under the next round or the series winner.
+pub fn display_series(series: &Series, world: &World, buffer: &mut WindowBuffer) {
+    text::dim(buffer);
+
+    let score = format!("{}-{}", series.wins[0], series.wins[1]);
+    let x = buffer.width().saturating_sub(text::text_width(&score)) / 2;
+    let y = buffer.height().saturating_sub(text::GLYPH_HEIGHT) / 2;
+    let title = match series.winner() {
+        Some(player) => format!("P{} WINS", player + 1),
+        None => format!("ROUND {}", series.round + 1),
+    };
+    let title_x = buffer.width().saturating_sub(text::text_width(&title)) / 2;
+
+    text::draw_text(
+        buffer,
+        &title,
+        title_x,
+        y.saturating_sub(text::GLYPH_HEIGHT + 2),
+        rgb(u8::MAX, u8::MAX, u8::MAX),
+    );
+    text::draw_text(
+        buffer,
+        &series.wins[0].to_string(),
+        x,
+        y,
+        world.first_snake_colour,
+    );
+    text::draw_text(
+        buffer,
+        "-",
+        x + text::GLYPH_WIDTH + 1,
+        y,
+        rgb(u8::MAX, u8::MAX, u8::MAX),
+    );
+    text::draw_text(
+        buffer,
+        &series.wins[1].to_string(),
+        x + 2 * (text::GLYPH_WIDTH + 1),
+        y,
+        world.second_snake_colour,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_world;
+    use crate::{display, snake_generator, Cli, Direction, EndReason};
+    use clap::Parser;
+    use insta::assert_snapshot;
+
+    fn won_by(winner: Option<usize>) -> VersusResult {
+        VersusResult {
+            winner,
+            reason: EndReason::HitOpponent,
+            lengths: [3, 3],
+        }
+    }
+
+    #[test]
+    fn first_to_a_majority_takes_the_series() {
+        let start = Instant::now();
+        let mut series = Series::new(3);
+        assert_eq!(series.wins_needed(), 2);
+        assert!(!series.sides_swapped());
+
+        series.record(&won_by(Some(1)), start);
+        assert!(!series.next_round(start));
+        assert!(series.next_round(start + INTERMISSION));
+        assert!(series.sides_swapped());
+        assert_snapshot!(series, @"Round 2 of best of 3 0-1");
+
+        series.record(&won_by(None), start);
+        assert!(series.next_round(start + INTERMISSION));
+        series.record(&won_by(Some(0)), start);
+        assert!(series.next_round(start + INTERMISSION));
+        assert!(series.sides_swapped());
+        series.record(&won_by(Some(1)), start);
+
+        assert!(series.is_over());
+        assert_eq!(series.intermission, None);
+        assert!(!series.next_round(start + INTERMISSION));
+        assert_snapshot!(series, @"Player 2 wins the series 1-2 (1 drawn)");
+    }
+
+    #[test]
+    fn sides_swap_and_the_score_shows_between_rounds() {
+        let cli = Cli::parse_from(["snake", "--two-players-mode", "--best-of", "3"]);
+        let mut buffer = WindowBuffer::new(32, 16);
+        let mut world = test_world(Direction::Still);
+        snake_generator(&mut world, &buffer, &cli);
+        swap_spawn_sides(&mut world);
+        assert_eq!(world.snake, vec![(13, 6), (14, 6), (15, 6)]);
+        assert_eq!(world.second_snake, Some(vec![(15, 8), (14, 8), (13, 8)]));
+
+        let mut series = Series::new(3);
+        series.record(&won_by(Some(0)), Instant::now());
+        display(&world, &mut buffer, &cli);
+        display_series(&series, &world, &mut buffer);
+        assert_snapshot!(
+            buffer.to_string(),
+            @r###"
+        #.##...#..#.#.##..##......###...
+        ..#.#.#.#.#.#.#.#.#.#.......#...
+        ..##..#.#.#.#.#.#.#.#.....###...
+        ..#.#.#.#.#.#.#.#.#.#.....#.....
+        ..#.#..#..###.#.#.##......###...
+        ...........#......###...........
+        ..........##.###..#.#...........
+        ...........#..###.#.#...........
+        ...........#.###..#.#...........
+        ..........###.....###...........
+        ................................
+        ................................
+        ................................
+        ................................
+        ................................
+        ................................
+        "###
+        );
+    }
+}
@@ -0,0 +1,658 @@
+//! Dedicated game server. It holds any number of named rooms, each seating a
+//! fixed number of players; a room's match starts as soon as it is full and
+//! is played out on an `Arena`, each snake moving at its own pace on a game
+//! clock that starts with the match. Spectators can follow a room, matches after
+//! matches. Clients talk to it in JSON lines like the rest of the network
+//! play.
+
+use crate::arena::{Arena, ArenaSettings, MatchResult};
+use crate::clock::Clock;
+use crate::connection::Connection;
+use crate::net::Snapshot;
+use crate::Direction;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use web_time::{Duration, Instant};
+
+/// How long the server sleeps between two polls when running on its own.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct RoomSettings {
+    pub name: String,
+    pub players: usize,
+    pub arena: ArenaSettings,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct RoomInfo {
+    pub name: String,
+    pub players: usize,
+    pub seated: Vec<String>,
+    pub playing: bool,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A finished match and the room it was played in.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MatchRecord {
+    pub room: String,
+    pub result: MatchResult,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Request {
+    ListRooms,
+    CreateRoom(RoomSettings),
+    JoinRoom { room: String, name: String },
+    Spectate { room: String },
+    Turn(Direction),
+    Results,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Response {
+    Rooms(Vec<RoomInfo>),
+    /// You have a seat in `room`, and will play snake number `player`.
+    Joined {
+        room: RoomSettings,
+        player: usize,
+    },
+    /// You are watching `room`, the board follows if a match is on.
+    Spectating {
+        room: RoomSettings,
+    },
+    /// The board once `tick` moves of the match have been played.
+    State {
+        tick: u64,
+        board: Box<Snapshot>,
+    },
+    MatchOver(MatchRecord),
+    Results(Vec<MatchRecord>),
+    Error(String),
+}
+
+struct Room {
+    settings: RoomSettings,
+    /// Ids of the seated clients, in player order.
+    seats: Vec<usize>,
+    spectators: Vec<usize>,
+    arena: Option<Arena>,
+    /// When the match started, game time 0 of its arena.
+    started: Instant,
+}
+
+impl Room {
+    fn info(&self, clients: &[ServerClient]) -> RoomInfo {
+        RoomInfo {
+            name: self.settings.name.clone(),
+            players: self.settings.players,
+            seated: self
+                .seats
+                .iter()
+                .filter_map(|id| clients.iter().find(|client| client.id == *id))
+                .map(|client| client.name.clone())
+                .collect(),
+            playing: self.arena.is_some(),
+            width: self.settings.arena.width,
+            height: self.settings.arena.height,
+        }
+    }
+}
+
+struct ServerClient {
+    id: usize,
+    name: String,
+    connection: Connection<Request>,
+}
+
+pub struct Server {
+    listener: TcpListener,
+    clients: Vec<ServerClient>,
+    rooms: Vec<Room>,
+    results: Vec<MatchRecord>,
+    clock: Arc<dyn Clock>,
+    next_id: usize,
+    /// Whether clients may open rooms of their own, on by default.
+    pub client_rooms: bool,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, clock: Arc<dyn Clock>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            rooms: Vec::new(),
+            results: Vec::new(),
+            clock,
+            next_id: 0,
+            client_rooms: true,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn create_room(&mut self, settings: RoomSettings) -> Result<(), String> {
+        settings
+            .arena
+            .check(settings.players)
+            .map_err(|error| format!("room {}: {error}", settings.name))?;
+        if self
+            .rooms
+            .iter()
+            .any(|room| room.settings.name == settings.name)
+        {
+            return Err(format!("there is already a room called {}", settings.name));
+        }
+
+        self.rooms.push(Room {
+            settings,
+            seats: Vec::new(),
+            spectators: Vec::new(),
+            arena: None,
+            started: self.clock.now(),
+        });
+        Ok(())
+    }
+
+    pub fn rooms(&self) -> Vec<RoomInfo> {
+        self.rooms
+            .iter()
+            .map(|room| room.info(&self.clients))
+            .collect()
+    }
+
+    pub fn results(&self) -> &[MatchRecord] {
+        &self.results
+    }
+
+    /// Accepts newcomers, answers requests and ticks every room that is due.
+    /// Trouble with one client never stops the server, only a listener that
+    /// stopped working does.
+    pub fn poll(&mut self) -> io::Result<()> {
+        while let Some(connection) = Connection::accept(&self.listener)? {
+            self.clients.push(ServerClient {
+                id: self.next_id,
+                name: String::new(),
+                connection,
+            });
+            self.next_id += 1;
+        }
+
+        let mut index = 0;
+        while index < self.clients.len() {
+            match self.clients[index].connection.try_receive() {
+                Ok(Some(request)) => {
+                    let id = self.clients[index].id;
+                    self.handle(id, request);
+                }
+                Ok(None) => index += 1,
+                Err(_) => {
+                    let id = self.clients[index].id;
+                    self.disconnect(id);
+                }
+            }
+        }
+
+        self.tick_rooms();
+        Ok(())
+    }
+
+    /// Serves forever.
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.poll()?;
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn send(&mut self, id: usize, response: &Response) {
+        let Some(client) = self.clients.iter_mut().find(|client| client.id == id) else {
+            return;
+        };
+        if client.connection.send(response).is_err() {
+            self.disconnect(id);
+        }
+    }
+
+    fn handle(&mut self, id: usize, request: Request) {
+        let seat = self.seat_of(id);
+
+        match request {
+            Request::ListRooms => self.send(id, &Response::Rooms(self.rooms())),
+            Request::Results => self.send(id, &Response::Results(self.results.clone())),
+            Request::CreateRoom(_) if !self.client_rooms => self.send(
+                id,
+                &Response::Error("rooms can't be created on this server".to_string()),
+            ),
+            Request::CreateRoom(settings) => match self.create_room(settings) {
+                Ok(()) => self.send(id, &Response::Rooms(self.rooms())),
+                Err(error) => self.send(id, &Response::Error(error)),
+            },
+            Request::JoinRoom { room, name } => {
+                let Some(index) = self.rooms.iter().position(|r| r.settings.name == room) else {
+                    return self.send(id, &Response::Error(format!("no room called {room}")));
+                };
+                let error = if seat.is_some() {
+                    Some("you are already seated in a room")
+                } else if self.rooms[index].arena.is_some() {
+                    Some("a match is being played in that room")
+                } else if self.rooms[index].seats.len() >= self.rooms[index].settings.players {
+                    Some("that room is full")
+                } else {
+                    None
+                };
+                if let Some(error) = error {
+                    return self.send(id, &Response::Error(error.to_string()));
+                }
+
+                if let Some(client) = self.clients.iter_mut().find(|client| client.id == id) {
+                    client.name = name;
+                }
+                let room = &mut self.rooms[index];
+                room.seats.push(id);
+                let joined = Response::Joined {
+                    room: room.settings.clone(),
+                    player: room.seats.len() - 1,
+                };
+                self.send(id, &joined);
+
+                if self.rooms[index].seats.len() == self.rooms[index].settings.players {
+                    self.start(index);
+                }
+            }
+            Request::Spectate { room } => {
+                let Some(index) = self.rooms.iter().position(|r| r.settings.name == room) else {
+                    return self.send(id, &Response::Error(format!("no room called {room}")));
+                };
+                if seat.is_some() {
+                    return self.send(id, &Response::Error("you are seated in a room".to_string()));
+                }
+
+                let room = &mut self.rooms[index];
+                if !room.spectators.contains(&id) {
+                    room.spectators.push(id);
+                }
+                let spectating = Response::Spectating {
+                    room: room.settings.clone(),
+                };
+                let state = room.arena.as_ref().map(state);
+                self.send(id, &spectating);
+                if let Some(state) = state {
+                    self.send(id, &state);
+                }
+            }
+            Request::Turn(direction) => {
+                if let Some((index, player)) = seat {
+                    if let Some(arena) = self.rooms[index].arena.as_mut() {
+                        arena.turn(player, direction);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Room index and player number of a seated client.
+    fn seat_of(&self, id: usize) -> Option<(usize, usize)> {
+        self.rooms.iter().enumerate().find_map(|(index, room)| {
+            let player = room.seats.iter().position(|seat| *seat == id)?;
+            Some((index, player))
+        })
+    }
+
+    fn disconnect(&mut self, id: usize) {
+        for room in self.rooms.iter_mut() {
+            room.spectators.retain(|spectator| *spectator != id);
+        }
+        if let Some((index, player)) = self.seat_of(id) {
+            let room = &mut self.rooms[index];
+            match room.arena.as_mut() {
+                Some(arena) => arena.forfeit(player),
+                None => {
+                    room.seats.remove(player);
+                }
+            }
+        }
+        self.clients.retain(|client| client.id != id);
+    }
+
+    fn start(&mut self, index: usize) {
+        let names = self.rooms[index]
+            .seats
+            .iter()
+            .map(|id| {
+                self.clients
+                    .iter()
+                    .find(|client| client.id == *id)
+                    .map(|client| client.name.clone())
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let room = &mut self.rooms[index];
+        // The room's board was checked when it was created.
+        let Ok(arena) = Arena::new(room.settings.arena.clone(), names, rand::thread_rng().gen())
+        else {
+            return;
+        };
+        room.started = self.clock.now();
+        let state = state(&arena);
+        room.arena = Some(arena);
+        self.send_to_room(index, &state);
+    }
+
+    /// Sends to the players and the spectators of a room.
+    fn send_to_room(&mut self, index: usize, response: &Response) {
+        let room = &self.rooms[index];
+        for id in [room.seats.clone(), room.spectators.clone()].concat() {
+            self.send(id, response);
+        }
+    }
+
+    fn tick_rooms(&mut self) {
+        let now = self.clock.now();
+
+        for index in 0..self.rooms.len() {
+            let room = &mut self.rooms[index];
+            let Some(arena) = room.arena.as_mut() else {
+                continue;
+            };
+            let mut moved = false;
+            while let Some((_, at)) = arena.next_move() {
+                if room.started + at > now {
+                    break;
+                }
+                arena.step();
+                moved = true;
+            }
+            if !moved && !arena.finished() {
+                continue;
+            }
+
+            let board = state(arena);
+            let result = arena.finished().then(|| arena.result());
+            self.send_to_room(index, &board);
+
+            if let Some(result) = result {
+                let record = MatchRecord {
+                    room: self.rooms[index].settings.name.clone(),
+                    result,
+                };
+                self.send_to_room(index, &Response::MatchOver(record.clone()));
+                self.results.push(record);
+
+                let room = &mut self.rooms[index];
+                room.arena = None;
+                room.seats.clear();
+            }
+        }
+    }
+}
+
+/// The board of `arena`, to send to a room.
+fn state(arena: &Arena) -> Response {
+    Response::State {
+        tick: arena.tick,
+        board: Box::new(Snapshot::from_world(&arena.world)),
+    }
+}
+
+/// A player's side of a connection to the server.
+pub struct Client {
+    connection: Connection<Response>,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self {
+            connection: Connection::new(TcpStream::connect(addr)?)?,
+        })
+    }
+
+    pub fn send(&mut self, request: &Request) -> io::Result<()> {
+        self.connection.send(request)
+    }
+
+    pub fn try_receive(&mut self) -> io::Result<Option<Response>> {
+        self.connection.try_receive()
+    }
+
+    pub fn receive(&mut self) -> io::Result<Response> {
+        self.connection.receive()
+    }
+
+    /// Sends `request` and waits for the answer, skipping any game updates
+    /// that arrive in between.
+    pub fn request(&mut self, request: &Request) -> io::Result<Response> {
+        self.send(request)?;
+        loop {
+            match self.receive()? {
+                Response::State { .. } | Response::MatchOver(_) => continue,
+                Response::Error(error) => return Err(io::Error::other(error)),
+                response => return Ok(response),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::thread;
+
+    fn room(name: &str, players: usize) -> RoomSettings {
+        RoomSettings {
+            name: name.to_string(),
+            players,
+            arena: ArenaSettings {
+                width: 12,
+                height: 9,
+                ..ArenaSettings::default()
+            },
+        }
+    }
+
+    /// Polls the server from another thread while `client` waits for a
+    /// response, so the test doesn't have to interleave both by hand.
+    fn serve_until<T: Send>(server: &mut Server, client: impl FnOnce() -> T + Send) -> T {
+        thread::scope(|scope| {
+            let waiting = scope.spawn(client);
+            while !waiting.is_finished() {
+                server.poll().unwrap();
+            }
+            waiting.join().unwrap()
+        })
+    }
+
+    #[test]
+    fn rooms_are_listed_and_played() {
+        let clock = Arc::new(ManualClock::new());
+        let mut server = Server::bind("127.0.0.1:0", clock.clone()).unwrap();
+        let addr = server.local_addr().unwrap();
+        server.create_room(room("office", 2)).unwrap();
+        assert!(server.create_room(room("office", 3)).is_err());
+
+        let mut alice = Client::connect(addr).unwrap();
+        let mut bob = Client::connect(addr).unwrap();
+
+        let rooms = serve_until(&mut server, || alice.request(&Request::ListRooms).unwrap());
+        let Response::Rooms(rooms) = rooms else {
+            panic!("expected the rooms, got {rooms:?}");
+        };
+        assert_eq!(rooms.len(), 1);
+        assert_eq!((rooms[0].players, rooms[0].playing), (2, false));
+
+        let joined = serve_until(&mut server, || {
+            alice
+                .request(&Request::JoinRoom {
+                    room: "office".to_string(),
+                    name: "alice".to_string(),
+                })
+                .unwrap()
+        });
+        assert!(matches!(joined, Response::Joined { player: 0, .. }));
+        assert_eq!(server.rooms()[0].seated, vec!["alice".to_string()]);
+
+        let error = serve_until(&mut server, || {
+            alice
+                .request(&Request::JoinRoom {
+                    room: "office".to_string(),
+                    name: "alice".to_string(),
+                })
+                .is_err()
+        });
+        assert!(error);
+
+        let first_state = serve_until(&mut server, || {
+            bob.send(&Request::JoinRoom {
+                room: "office".to_string(),
+                name: "bob".to_string(),
+            })
+            .unwrap();
+            assert!(matches!(
+                bob.receive().unwrap(),
+                Response::Joined { player: 1, .. }
+            ));
+            bob.receive().unwrap()
+        });
+        let Response::State { tick: 0, board } = first_state else {
+            panic!("expected the board, got {first_state:?}");
+        };
+        assert_eq!(board.second_snake.map(|snake| snake.len()), Some(3));
+        assert!(server.rooms()[0].playing);
+
+        // Bob heads straight into alice, who doesn't move.
+        let speed = ArenaSettings::default().difficulty.starting_speed;
+        let record = serve_until(&mut server, || {
+            bob.send(&Request::Turn(Direction::South)).unwrap();
+            loop {
+                clock.advance(Duration::from_millis(speed as u64));
+                if let Response::MatchOver(record) = bob.receive().unwrap() {
+                    return record;
+                }
+            }
+        });
+        assert_eq!(record.room, "office");
+        assert_eq!(record.result.winner, Some(0));
+        assert_eq!(record.result.players[0].name, "alice");
+        assert_eq!(server.results(), std::slice::from_ref(&record));
+        assert!(!server.rooms()[0].playing);
+
+        let results = serve_until(&mut server, || bob.request(&Request::Results).unwrap());
+        assert!(matches!(results, Response::Results(results) if results == vec![record]));
+    }
+
+    #[test]
+    fn client_rooms_can_be_switched_off() {
+        let clock = Arc::new(ManualClock::new());
+        let mut server = Server::bind("127.0.0.1:0", clock).unwrap();
+        let mut client = Client::connect(server.local_addr().unwrap()).unwrap();
+        let mut create = |server: &mut Server, settings: RoomSettings| {
+            serve_until(server, || client.request(&Request::CreateRoom(settings)))
+        };
+
+        server.client_rooms = false;
+        assert!(create(&mut server, room("den", 2)).is_err());
+        server.client_rooms = true;
+        let mut huge = room("hangar", 2);
+        huge.arena.width = 10_000;
+        assert!(create(&mut server, huge).is_err());
+        assert!(create(&mut server, room("den", 2)).is_ok());
+        assert_eq!(server.rooms().len(), 1);
+    }
+
+    #[test]
+    fn spectators_follow_a_match_in_progress() {
+        let clock = Arc::new(ManualClock::new());
+        let mut server = Server::bind("127.0.0.1:0", clock.clone()).unwrap();
+        let addr = server.local_addr().unwrap();
+        server.create_room(room("solo", 1)).unwrap();
+
+        let mut player = Client::connect(addr).unwrap();
+        serve_until(&mut server, || {
+            player
+                .request(&Request::JoinRoom {
+                    room: "solo".to_string(),
+                    name: "alice".to_string(),
+                })
+                .unwrap()
+        });
+        clock.advance(Duration::from_secs(1));
+        serve_until(&mut server, || {
+            while !matches!(player.receive().unwrap(), Response::State { tick, .. } if tick > 0) {}
+        });
+
+        let mut spectator = Client::connect(addr).unwrap();
+        let (spectating, board) = serve_until(&mut server, || {
+            spectator
+                .send(&Request::Spectate {
+                    room: "solo".to_string(),
+                })
+                .unwrap();
+            (spectator.receive().unwrap(), spectator.receive().unwrap())
+        });
+        assert!(matches!(spectating, Response::Spectating { .. }));
+        // A second of moves at the starting speed.
+        let Response::State { tick: 8, board } = board else {
+            panic!("expected the board, got {board:?}");
+        };
+        assert_eq!(server.rooms()[0].seated, vec!["alice".to_string()]);
+
+        // Turns from a spectator go nowhere.
+        let next = serve_until(&mut server, || {
+            spectator.send(&Request::Turn(Direction::North)).unwrap();
+            clock.advance(Duration::from_secs(1));
+            spectator.receive().unwrap()
+        });
+        let Response::State {
+            tick: 16,
+            board: next,
+        } = next
+        else {
+            panic!("expected the board, got {next:?}");
+        };
+        assert_eq!(next.snake, board.snake);
+    }
+
+    #[test]
+    fn leaving_a_match_forfeits_it() {
+        let clock = Arc::new(ManualClock::new());
+        let mut server = Server::bind("127.0.0.1:0", clock.clone()).unwrap();
+        let addr = server.local_addr().unwrap();
+        server.create_room(room("lunch", 2)).unwrap();
+
+        let mut players: Vec<Client> = (0..2).map(|_| Client::connect(addr).unwrap()).collect();
+        for (player, client) in players.iter_mut().enumerate() {
+            serve_until(&mut server, || {
+                client
+                    .request(&Request::JoinRoom {
+                        room: "lunch".to_string(),
+                        name: format!("player {player}"),
+                    })
+                    .unwrap()
+            });
+        }
+        assert!(server.rooms()[0].playing);
+
+        let mut first = players.remove(0);
+        drop(players);
+        let record = serve_until(&mut server, || loop {
+            clock.advance(Duration::from_millis(200));
+            if let Response::MatchOver(record) = first.receive().unwrap() {
+                return record;
+            }
+        });
+        assert_eq!(record.result.winner, Some(0));
+        assert!(!record.result.players[1].alive);
+    }
+}
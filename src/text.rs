@@ -0,0 +1,123 @@
+use window_rs::WindowBuffer;
+
+/// Width of a glyph in pixels, without the spacing column.
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+
+/// 3x5 bitmap for a character, one row per entry, leftmost pixel in the
+/// highest bit. Lowercase letters are drawn as uppercase and anything else
+/// unknown comes out blank.
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT] {
+    match character.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+/// Width in pixels of `text` once drawn, with one blank column between characters.
+pub fn text_width(text: &str) -> usize {
+    let characters = text.chars().count();
+    (characters * (GLYPH_WIDTH + 1)).saturating_sub(1)
+}
+
+/// Draws `text` with its top left corner at `(x, y)`. Whatever falls outside
+/// the buffer is clipped.
+pub fn draw_text(buffer: &mut WindowBuffer, text: &str, x: usize, y: usize, colour: u32) {
+    for (index, character) in text.chars().enumerate() {
+        let left = x + index * (GLYPH_WIDTH + 1);
+
+        for (row, bits) in glyph(character).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                let (pixel_x, pixel_y) = (left + column, y + row);
+                if bits & (0b100 >> column) != 0
+                    && pixel_x < buffer.width()
+                    && pixel_y < buffer.height()
+                {
+                    buffer[(pixel_x, pixel_y)] = colour;
+                }
+            }
+        }
+    }
+}
+
+/// Draws `text` centred on the buffer.
+pub fn draw_centered_text(buffer: &mut WindowBuffer, text: &str, colour: u32) {
+    let x = buffer.width().saturating_sub(text_width(text)) / 2;
+    let y = buffer.height().saturating_sub(GLYPH_HEIGHT) / 2;
+    draw_text(buffer, text, x, y, colour);
+}
+
+/// Halves the brightness of everything already drawn.
+pub fn dim(buffer: &mut WindowBuffer) {
+    for y in 0..buffer.height() {
+        for x in 0..buffer.width() {
+            buffer[(x, y)] = (buffer[(x, y)] >> 1) & 0x007F7F7F;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use insta::assert_snapshot;
+
+    #[test]
+    fn text_is_centred_and_clipped() {
+        let mut buffer = WindowBuffer::new(13, 7);
+        draw_centered_text(&mut buffer, "P1", 0xFFFFFF);
+        draw_text(&mut buffer, "7", 11, 4, 0xFFFFFF);
+
+        assert_snapshot!(
+            buffer.to_string(),
+            @r###"
+        .............
+        ...##...#....
+        ...#.#.##....
+        ...##...#....
+        ...#....#..##
+        ...#...###...
+        .............
+        "###
+        );
+        assert_eq!(text_width("PAUSED"), 23);
+    }
+}
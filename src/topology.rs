@@ -0,0 +1,133 @@
+//! How the edges of the board are joined. Every move goes through
+//! `Topology::step`, so the snakes, the arena and the bots all agree on where
+//! a snake leaving the board comes back, if it does.
+use crate::Direction;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, ValueEnum, Debug, Default, Serialize, Deserialize)]
+pub enum Topology {
+    /// Walls all around.
+    #[default]
+    Bounded,
+    /// Every edge wraps to the opposite one, like in ghost mode.
+    Torus,
+    /// Only the left and right edges wrap.
+    Horizontal,
+    /// Only the top and bottom edges wrap.
+    Vertical,
+    /// The left and right edges wrap upside down, the others are walls.
+    Mobius,
+    /// The left and right edges wrap upside down, the others wrap as usual.
+    Klein,
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Topology::Bounded => write!(f, "bounded"),
+            Topology::Torus => write!(f, "torus"),
+            Topology::Horizontal => write!(f, "horizontal"),
+            Topology::Vertical => write!(f, "vertical"),
+            Topology::Mobius => write!(f, "mobius"),
+            Topology::Klein => write!(f, "klein"),
+        }
+    }
+}
+
+impl Topology {
+    fn wraps_sideways(&self) -> bool {
+        !matches!(self, Topology::Bounded | Topology::Vertical)
+    }
+
+    fn wraps_up_and_down(&self) -> bool {
+        matches!(self, Topology::Torus | Topology::Vertical | Topology::Klein)
+    }
+
+    /// Whether going through a side edge turns the board upside down.
+    fn flips(&self) -> bool {
+        matches!(self, Topology::Mobius | Topology::Klein)
+    }
+
+    /// Where something at `position` moving towards `direction` ends up on a
+    /// `width` by `height` board, `None` if that is into a wall. Whatever
+    /// comes back through a flipped edge keeps its heading.
+    pub fn step(
+        &self,
+        position: (usize, usize),
+        direction: &Direction,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
+        let (x, y) = position;
+
+        let moved = match direction {
+            Direction::North => y.checked_sub(1).map(|y| (x, y)),
+            Direction::South => Some((x, y + 1)).filter(|(_, y)| *y < height),
+            Direction::West => x.checked_sub(1).map(|x| (x, y)),
+            Direction::East => Some((x + 1, y)).filter(|(x, _)| *x < width),
+            Direction::Still => Some(position),
+        };
+        if moved.is_some() {
+            return moved;
+        }
+
+        let row = if self.flips() { height - 1 - y } else { y };
+        match direction {
+            Direction::North if self.wraps_up_and_down() => Some((x, height - 1)),
+            Direction::South if self.wraps_up_and_down() => Some((x, 0)),
+            Direction::West if self.wraps_sideways() => Some((width - 1, row)),
+            Direction::East if self.wraps_sideways() => Some((0, row)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn edges_join_by_topology() {
+        let edges = [
+            ((4, 0), Direction::North),
+            ((4, 5), Direction::South),
+            ((0, 1), Direction::West),
+            ((9, 1), Direction::East),
+        ];
+        let landings = |topology: Topology| {
+            edges
+                .iter()
+                .map(|(position, direction)| topology.step(*position, direction, 10, 6))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(landings(Topology::Bounded), vec![None; 4]);
+        assert_eq!(
+            landings(Topology::Torus),
+            vec![Some((4, 5)), Some((4, 0)), Some((9, 1)), Some((0, 1))]
+        );
+        assert_eq!(
+            landings(Topology::Horizontal),
+            vec![None, None, Some((9, 1)), Some((0, 1))]
+        );
+        assert_eq!(
+            landings(Topology::Vertical),
+            vec![Some((4, 5)), Some((4, 0)), None, None]
+        );
+        assert_eq!(
+            landings(Topology::Mobius),
+            vec![None, None, Some((9, 4)), Some((0, 4))]
+        );
+        assert_eq!(
+            landings(Topology::Klein),
+            vec![Some((4, 5)), Some((4, 0)), Some((9, 4)), Some((0, 4))]
+        );
+
+        assert_eq!(
+            Topology::Klein.step((3, 3), &Direction::East, 10, 6),
+            Some((4, 3))
+        );
+    }
+}
@@ -0,0 +1,408 @@
+//! Round robin tournaments between bots. Every pair of bots meets once per
+//! seed and board size, matches are spread over a few threads, and the
+//! results are summed up as win/loss/draw counts, average scores and Elo
+//! ratings.
+
+use crate::arena::{Arena, ArenaSettings, MatchResult};
+use crate::bot::{self, BotMatch, OnFailure};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use web_time::Duration;
+
+/// Rating every bot starts the tournament with.
+pub const INITIAL_RATING: f64 = 1000.0;
+/// How many points a single match can move a rating by.
+pub const RATING_K_FACTOR: f64 = 32.0;
+
+#[derive(Debug, Clone)]
+pub struct TournamentSettings {
+    /// Bot specs, as understood by `bot::connect`.
+    pub bots: Vec<String>,
+    pub seeds: Vec<u64>,
+    pub sizes: Vec<(usize, usize)>,
+    /// Everything but the board size, which comes from `sizes`.
+    pub arena: ArenaSettings,
+    pub budget: Duration,
+    pub on_failure: OnFailure,
+    pub max_ticks: u64,
+    pub threads: usize,
+}
+
+/// One match to play: the bots playing the first and second snake.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Fixture {
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    pub players: [usize; 2],
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct MatchReport {
+    pub fixture: Fixture,
+    pub result: MatchResult,
+}
+
+impl MatchReport {
+    /// 1 for a win, 0.5 for a draw and 0 for a loss, from the point of view
+    /// of the first snake.
+    fn first_player_score(&self) -> f64 {
+        match self.result.winner {
+            Some(0) => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Standing {
+    pub bot: String,
+    pub played: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+    pub average_score: f64,
+    pub rating: f64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TournamentReport {
+    /// Best rated first.
+    pub standings: Vec<Standing>,
+    pub matches: Vec<MatchReport>,
+}
+
+/// Every pair of bots on every board size and seed. Bots swap sides from one
+/// seed to the next.
+pub fn schedule(settings: &TournamentSettings) -> Vec<Fixture> {
+    let mut fixtures = Vec::new();
+    let bots = settings.bots.len();
+
+    for (width, height) in settings.sizes.iter().copied() {
+        for (round, seed) in settings.seeds.iter().copied().enumerate() {
+            for first in 0..bots {
+                for second in first + 1..bots {
+                    let players = if round % 2 == 0 {
+                        [first, second]
+                    } else {
+                        [second, first]
+                    };
+                    fixtures.push(Fixture {
+                        seed,
+                        width,
+                        height,
+                        players,
+                    });
+                }
+            }
+        }
+    }
+    fixtures
+}
+
+fn play(settings: &TournamentSettings, fixture: &Fixture) -> io::Result<MatchResult> {
+    let bots = fixture
+        .players
+        .iter()
+        .map(|player| bot::connect(&settings.bots[*player], settings.budget))
+        .collect::<io::Result<Vec<_>>>()?;
+    let names = fixture
+        .players
+        .iter()
+        .map(|player| settings.bots[*player].clone())
+        .collect();
+
+    let arena_settings = ArenaSettings {
+        width: fixture.width,
+        height: fixture.height,
+        ..settings.arena.clone()
+    };
+    let arena = Arena::new(arena_settings, names, fixture.seed)?;
+    Ok(BotMatch::new(arena, bots, settings.on_failure).play(settings.max_ticks))
+}
+
+/// Plays the whole tournament. Matches are reported in schedule order
+/// whatever order the threads finish them in, so ratings don't depend on
+/// timing.
+pub fn run(settings: &TournamentSettings) -> io::Result<TournamentReport> {
+    let fixtures = schedule(settings);
+    let next = Mutex::new(0);
+    let (sender, finished) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..settings.threads.max(1) {
+            let sender = sender.clone();
+            let (next, fixtures) = (&next, &fixtures);
+            scope.spawn(move || loop {
+                let index = {
+                    let mut next = next.lock().unwrap();
+                    *next += 1;
+                    *next - 1
+                };
+                let Some(fixture) = fixtures.get(index) else {
+                    break;
+                };
+                if sender.send((index, play(settings, fixture))).is_err() {
+                    break;
+                }
+            });
+        }
+    });
+    drop(sender);
+
+    let mut results: Vec<(usize, io::Result<MatchResult>)> = finished.into_iter().collect();
+    results.sort_by_key(|(index, _)| *index);
+
+    let matches = results
+        .into_iter()
+        .map(|(index, result)| {
+            Ok(MatchReport {
+                fixture: fixtures[index].clone(),
+                result: result?,
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok(TournamentReport {
+        standings: standings(&settings.bots, &matches),
+        matches,
+    })
+}
+
+/// Expected score of a player rated `rating` against one rated `opponent`.
+fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+/// Sums up `matches`, applying the rating changes in the order they were played.
+pub fn standings(bots: &[String], matches: &[MatchReport]) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = bots
+        .iter()
+        .map(|bot| Standing {
+            bot: bot.clone(),
+            played: 0,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+            average_score: 0.0,
+            rating: INITIAL_RATING,
+        })
+        .collect();
+    let mut total_scores = vec![0; bots.len()];
+
+    for report in matches {
+        let [first, second] = report.fixture.players;
+        let score = report.first_player_score();
+
+        let expected = expected_score(standings[first].rating, standings[second].rating);
+        let change = RATING_K_FACTOR * (score - expected);
+        standings[first].rating += change;
+        standings[second].rating -= change;
+
+        for (side, bot) in [first, second].into_iter().enumerate() {
+            let standing = &mut standings[bot];
+            standing.played += 1;
+            match report.result.winner {
+                None => standing.draws += 1,
+                Some(winner) if winner == side => standing.wins += 1,
+                Some(_) => standing.losses += 1,
+            }
+            total_scores[bot] += report.result.players[side].score;
+        }
+    }
+
+    for (standing, total) in standings.iter_mut().zip(total_scores) {
+        if standing.played > 0 {
+            standing.average_score = total as f64 / standing.played as f64;
+        }
+    }
+    standings.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+    standings
+}
+
+impl TournamentReport {
+    /// One line per match, with a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "seed,width,height,first,second,winner,first_score,second_score,first_length,second_length,ticks\n",
+        );
+        for report in self.matches.iter() {
+            let (first, second) = (&report.result.players[0], &report.result.players[1]);
+            let winner = report
+                .result
+                .winner
+                .map(|winner| report.result.players[winner].name.clone())
+                .unwrap_or_default();
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{},{},{}\n",
+                report.fixture.seed,
+                report.fixture.width,
+                report.fixture.height,
+                csv_field(&first.name),
+                csv_field(&second.name),
+                csv_field(&winner),
+                first.score,
+                second.score,
+                first.length,
+                second.length,
+                report.result.ticks
+            ));
+        }
+        csv
+    }
+}
+
+/// Quotes a field if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl fmt::Display for TournamentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .standings
+            .iter()
+            .map(|standing| standing.bot.len())
+            .chain(Some(3))
+            .max()
+            .unwrap_or_default();
+
+        write!(
+            f,
+            "{:<width$}  {:>6}  {:>4}  {:>4}  {:>4}  {:>9}  {:>6}",
+            "Bot", "Played", "Won", "Lost", "Drew", "Avg score", "Rating"
+        )?;
+        for standing in self.standings.iter() {
+            write!(
+                f,
+                "\n{:<width$}  {:>6}  {:>4}  {:>4}  {:>4}  {:>9.1}  {:>6.0}",
+                standing.bot,
+                standing.played,
+                standing.wins,
+                standing.losses,
+                standing.draws,
+                standing.average_score,
+                standing.rating
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a board size given as `WIDTHxHEIGHT`.
+pub fn parse_size(size: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("{size} is not a board size like 40x30");
+    let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+    Ok((
+        width.trim().parse().map_err(|_| invalid())?,
+        height.trim().parse().map_err(|_| invalid())?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::PlayerResult;
+    use insta::assert_snapshot;
+
+    fn settings(bots: &[&str]) -> TournamentSettings {
+        TournamentSettings {
+            bots: bots.iter().map(|bot| bot.to_string()).collect(),
+            seeds: vec![1, 2],
+            sizes: vec![(16, 10), (24, 14)],
+            arena: ArenaSettings::default(),
+            budget: bot::DEFAULT_BUDGET,
+            on_failure: OnFailure::Forfeit,
+            max_ticks: 300,
+            threads: 3,
+        }
+    }
+
+    fn report(players: [usize; 2], winner: Option<usize>, scores: [usize; 2]) -> MatchReport {
+        MatchReport {
+            fixture: Fixture {
+                seed: 0,
+                width: 10,
+                height: 10,
+                players,
+            },
+            result: MatchResult {
+                ticks: 10,
+                winner,
+                players: players
+                    .iter()
+                    .zip(scores)
+                    .map(|(player, score)| PlayerResult {
+                        name: ["a", "b, the second"][*player].to_string(),
+                        score,
+                        length: 3,
+                        alive: true,
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn every_pair_meets_on_every_board() {
+        let fixtures = schedule(&settings(&["a", "b", "c"]));
+        assert_eq!(fixtures.len(), 3 * 2 * 2);
+        assert_eq!(fixtures[0].players, [0, 1]);
+        assert_eq!(fixtures[3].players, [1, 0]);
+        assert_eq!((fixtures[6].width, fixtures[6].height), (24, 14));
+    }
+
+    #[test]
+    fn standings_and_ratings() {
+        let bots = vec!["a".to_string(), "b, the second".to_string()];
+        let matches = vec![
+            report([0, 1], Some(0), [30, 10]),
+            report([1, 0], Some(1), [0, 20]),
+            report([0, 1], None, [10, 10]),
+        ];
+        let report = TournamentReport {
+            standings: standings(&bots, &matches),
+            matches,
+        };
+
+        assert_eq!(report.standings[0].bot, "a");
+        assert_eq!(report.standings[0].wins, 2);
+        assert_eq!(report.standings[1].losses, 2);
+        assert_eq!(report.standings[1].draws, 1);
+        let total = report.standings[0].rating + report.standings[1].rating;
+        assert!((total - 2.0 * INITIAL_RATING).abs() < 1e-9);
+        assert_snapshot!(report.to_string(), @r###"
+        Bot            Played   Won  Lost  Drew  Avg score  Rating
+        a                   3     2     0     1       20.0    1028
+        b, the second       3     0     2     1        6.7     972
+        "###);
+        assert_snapshot!(report.to_csv(), @r###"
+        seed,width,height,first,second,winner,first_score,second_score,first_length,second_length,ticks
+        0,10,10,a,"b, the second",a,30,10,3,3,10
+        0,10,10,"b, the second",a,a,0,20,3,3,10
+        0,10,10,a,"b, the second",,10,10,3,3,10
+        "###);
+    }
+
+    #[test]
+    fn built_in_bots_play_a_tournament() {
+        let settings = settings(&["builtin:greedy", "builtin:random"]);
+        let report = run(&settings).unwrap();
+        assert_eq!(report.matches.len(), 4);
+        assert_eq!(report.standings.iter().map(|s| s.played).sum::<usize>(), 8);
+        let fixtures: Vec<Fixture> = report.matches.iter().map(|m| m.fixture.clone()).collect();
+        assert_eq!(fixtures, schedule(&settings));
+
+        assert!(run(&self::settings(&["builtin:greedy", "builtin:clever"])).is_err());
+        assert_eq!(parse_size("40x30"), Ok((40, 30)));
+        assert!(parse_size("40").is_err());
+    }
+}